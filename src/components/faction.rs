@@ -1,4 +1,6 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 // Component for ownership/faction information
 #[derive(Component, Debug, Clone)]
@@ -9,7 +11,7 @@ pub struct Ownership {
 }
 
 // Faction identification 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum FactionId {
     Player(u32),    // Specific player number
     Neutral,        // Neutral/passive units
@@ -17,8 +19,34 @@ pub enum FactionId {
     Environment,    // Map elements
 }
 
+impl FactionId {
+    // Display color used for unit markers, minimap dots and the like
+    pub fn color(&self) -> Color {
+        const PLAYER_COLORS: [(f32, f32, f32); 8] = [
+            (0.1, 0.2, 0.8),    // Blue
+            (0.8, 0.1, 0.1),    // Red
+            (0.1, 0.7, 0.7),    // Teal
+            (0.5, 0.1, 0.6),    // Purple
+            (0.9, 0.9, 0.1),    // Yellow
+            (0.9, 0.5, 0.1),    // Orange
+            (0.2, 0.8, 0.2),    // Green
+            (0.9, 0.5, 0.7),    // Pink
+        ];
+
+        match self {
+            FactionId::Player(number) => {
+                let (r, g, b) = PLAYER_COLORS[(number.saturating_sub(1) % 8) as usize];
+                Color::srgb(r, g, b)
+            }
+            FactionId::Neutral => Color::srgb(0.7, 0.7, 0.7),
+            FactionId::Creep => Color::srgb(0.5, 0.1, 0.1),
+            FactionId::Environment => Color::srgb(0.6, 0.6, 0.4),
+        }
+    }
+}
+
 // Team grouping (alliances)
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TeamId {
     Team(u32),      // Specific team number
     Neutral,        // No team affiliation
@@ -26,7 +54,7 @@ pub enum TeamId {
 }
 
 // Controller type
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ControllerType {
     Human,          // Controlled by human player
     AI,             // Controlled by AI
@@ -41,4 +69,69 @@ impl Default for Ownership {
             controller_type: ControllerType::Automatic,
         }
     }
-}
\ No newline at end of file
+}
+
+// Gold and lumber held by each faction
+#[derive(Resource, Debug, Clone, Default)]
+pub struct PlayerResources {
+    pub stockpiles: HashMap<FactionId, Stockpile>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stockpile {
+    pub gold: i32,
+    pub lumber: i32,
+}
+
+impl PlayerResources {
+    // Add (or with negative amounts, remove) resources, never going below zero
+    pub fn give(&mut self, faction: &FactionId, gold: i32, lumber: i32) {
+        let stockpile = self.stockpiles.entry(faction.clone()).or_default();
+        stockpile.gold = (stockpile.gold + gold).max(0);
+        stockpile.lumber = (stockpile.lumber + lumber).max(0);
+    }
+
+    pub fn get(&self, faction: &FactionId) -> Stockpile {
+        self.stockpiles.get(faction).copied().unwrap_or_default()
+    }
+}
+
+// Resource identifying the player controlled on this machine
+#[derive(Resource, Debug, Clone)]
+pub struct LocalPlayer {
+    pub faction: FactionId,
+    pub team: TeamId,
+}
+
+impl Default for LocalPlayer {
+    fn default() -> Self {
+        Self {
+            faction: FactionId::Player(1),
+            team: TeamId::Team(1),
+        }
+    }
+}
+
+impl LocalPlayer {
+    // Units of the local player or its allies are never hidden by fog
+    pub fn is_friendly(&self, ownership: &Ownership) -> bool {
+        ownership.faction == self.faction
+            || matches!((&self.team, &ownership.team), (TeamId::Team(a), TeamId::Team(b)) if a == b)
+    }
+}
+
+impl Ownership {
+    // Same faction, or same numbered team
+    pub fn is_allied_with(&self, other: &Ownership) -> bool {
+        if self.faction == other.faction {
+            return true;
+        }
+        matches!((&self.team, &other.team), (TeamId::Team(a), TeamId::Team(b)) if a == b)
+    }
+
+    // Not allied, and neither side is passive (neutral units or map elements)
+    pub fn is_hostile_to(&self, other: &Ownership) -> bool {
+        let passive = |faction: &FactionId| matches!(faction, FactionId::Neutral | FactionId::Environment);
+        !self.is_allied_with(other) && !passive(&self.faction) && !passive(&other.faction)
+    }
+}
@@ -1,9 +1,11 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use crate::components::faction::Ownership;
 
-// Core unit identity - requires Statsheet and Ownership components
+// Core unit identity - requires Statsheet, Ownership and Steering components
 #[derive(Component, Debug, Clone)]
-#[require(Statsheet, Ownership)]
+#[require(Statsheet, Ownership, Steering)]
 pub struct Unit {
     pub name: String, 
     pub unit_type: UnitType,
@@ -11,6 +13,10 @@ pub struct Unit {
     pub target: Option<Entity>,
 }
 
+// Marker for units currently selected by the local player
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Selected;
+
 // Unified stat sheet for all unit attributes and derived stats
 #[derive(Component, Debug, Clone)]
 pub struct Statsheet {
@@ -47,10 +53,30 @@ pub struct Statsheet {
     // Other properties
     pub turn_rate: f32,
     pub sight_range: f32,
+    pub collision_radius: f32,
+}
+
+// Height of an entity's origin above the ground; keeps it on the terrain surface
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct GroundOffset(pub f32);
+
+// World positions a moving unit still has to walk to, next one first; only X and Z are used
+#[derive(Component, Debug, Clone, Default)]
+pub struct MovePath {
+    pub waypoints: VecDeque<Vec3>,
+}
+
+// Planar (XZ) velocities used for local collision avoidance
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Steering {
+    // Velocity the unit wants, straight along its path
+    pub preferred: Vec2,
+    // Velocity it moves with after making room for other units
+    pub velocity: Vec2,
 }
 
 // Unit type categorization
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum UnitType {
     Hero,
     Building,
@@ -136,6 +162,72 @@ impl Statsheet {
         self.health = self.max_health;
         self.mana = self.max_mana;
     }
+
+    // Read a numeric stat by field name (used by map scripts)
+    pub fn get_stat(&self, name: &str) -> Option<f32> {
+        let value = match name {
+            "strength" => self.strength,
+            "agility" => self.agility,
+            "intelligence" => self.intelligence,
+            "base_health" => self.base_health,
+            "base_mana" => self.base_mana,
+            "base_damage" => self.base_damage,
+            "base_armor" => self.base_armor,
+            "base_attack_speed" => self.base_attack_speed,
+            "base_move_speed" => self.base_move_speed,
+            "health" => self.health,
+            "mana" => self.mana,
+            "max_health" => self.max_health,
+            "max_mana" => self.max_mana,
+            "damage" => self.damage,
+            "armor" => self.armor,
+            "attack_speed" => self.attack_speed,
+            "move_speed" => self.move_speed,
+            "attack_range" => self.attack_range,
+            "turn_rate" => self.turn_rate,
+            "sight_range" => self.sight_range,
+            "collision_radius" => self.collision_radius,
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    // Write a numeric stat by field name; changing an attribute or base stat
+    // recalculates the derived stats. Returns false for unknown names.
+    pub fn set_stat(&mut self, name: &str, value: f32) -> bool {
+        let (field, derives) = match name {
+            "strength" => (&mut self.strength, true),
+            "agility" => (&mut self.agility, true),
+            "intelligence" => (&mut self.intelligence, true),
+            "base_health" => (&mut self.base_health, true),
+            "base_mana" => (&mut self.base_mana, true),
+            "base_damage" => (&mut self.base_damage, true),
+            "base_armor" => (&mut self.base_armor, true),
+            "base_attack_speed" => (&mut self.base_attack_speed, true),
+            "base_move_speed" => (&mut self.base_move_speed, true),
+            "health" => (&mut self.health, false),
+            "mana" => (&mut self.mana, false),
+            "max_health" => (&mut self.max_health, false),
+            "max_mana" => (&mut self.max_mana, false),
+            "damage" => (&mut self.damage, false),
+            "armor" => (&mut self.armor, false),
+            "attack_speed" => (&mut self.attack_speed, false),
+            "move_speed" => (&mut self.move_speed, false),
+            "attack_range" => (&mut self.attack_range, false),
+            "turn_rate" => (&mut self.turn_rate, false),
+            "sight_range" => (&mut self.sight_range, false),
+            "collision_radius" => (&mut self.collision_radius, false),
+            _ => return false,
+        };
+        *field = value;
+
+        if derives {
+            self.calculate_derived_stats();
+            self.health = self.health.min(self.max_health);
+            self.mana = self.mana.min(self.max_mana);
+        }
+        true
+    }
 }
 
 impl Default for Statsheet {
@@ -174,6 +266,7 @@ impl Default for Statsheet {
             // Other properties
             turn_rate: 0.5,
             sight_range: 10.0,
+            collision_radius: 0.3,
         };
         
         // Calculate derived stats
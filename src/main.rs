@@ -2,14 +2,57 @@ mod plugins;
 mod components;
 
 use bevy::prelude::*;
+use plugins::blight::BlightPlugin;
 use plugins::camera::CameraPlugin;
+use plugins::debug::DebugOverlayPlugin;
+use plugins::editor::EditorPlugin;
+use plugins::fog::FogOfWarPlugin;
 use plugins::map::MapPlugin;
+use plugins::minimap::MinimapPlugin;
+use plugins::scripting::ScriptingPlugin;
+use plugins::triggers::TriggerPlugin;
+use plugins::units::UnitsPlugin;
 
 
 fn main() {
+    // `runman preview <map> [pixels per cell]` exports a map thumbnail without starting the game
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|command| command == "preview") {
+        export_preview_command(&args[1..]);
+        return;
+    }
+
     App::new()
         .add_plugins(DefaultPlugins)
         .add_plugins(CameraPlugin)
         .add_plugins(MapPlugin)
+        .add_plugins(UnitsPlugin)
+        .add_plugins(FogOfWarPlugin)
+        .add_plugins(BlightPlugin)
+        .add_plugins(MinimapPlugin)
+        .add_plugins(DebugOverlayPlugin)
+        .add_plugins(EditorPlugin)
+        .add_plugins(TriggerPlugin)
+        .add_plugins(ScriptingPlugin)
         .run();
 }
+
+/// Write the preview image of a map file, for map browsers and build scripts
+fn export_preview_command(args: &[String]) {
+    let Some(map_name) = args.first() else {
+        eprintln!("usage: runman preview <map> [pixels per cell]");
+        std::process::exit(2);
+    };
+    let scale = args
+        .get(1)
+        .and_then(|scale| scale.parse().ok())
+        .unwrap_or(plugins::map::DEFAULT_PREVIEW_SCALE);
+
+    match plugins::map::export_preview(map_name, scale) {
+        Ok(path) => println!("Wrote {}", path.display()),
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
+use std::collections::{HashMap, HashSet};
+use crate::components::faction::{Ownership, FactionId};
+use crate::plugins::map::{GridCoord, GridCells, MapGrid, TerrainUpdate};
+use crate::plugins::units::Corpse;
+
+pub mod render;
+
+/// How fast blight recedes once its source is gone, in cells per second
+const RECEDE_RATE: f32 = 0.5;
+
+/// Spreads its owner's blight over the ground around it
+#[derive(Component, Debug, Clone)]
+pub struct BlightSource {
+    /// Radius in cells the blight grows out to
+    pub radius: f32,
+    /// Growth in cells per second
+    pub spread_rate: f32,
+}
+
+impl Default for BlightSource {
+    fn default() -> Self {
+        Self {
+            radius: 6.0,
+            spread_rate: 0.5,
+        }
+    }
+}
+
+/// Current extent of the blight around one source
+#[derive(Debug, Clone)]
+struct BlightSpread {
+    faction: FactionId,
+    center: GridCoord,
+    radius: f32,
+    max_radius: f32,
+    spread_rate: f32,
+    /// False once the source has died or lost its `BlightSource`
+    alive: bool,
+}
+
+/// Resource holding which faction's blight covers each cell
+#[derive(Resource, Default)]
+pub struct BlightLayer {
+    width: i32,
+    height: i32,
+    cells: Vec<Option<FactionId>>,
+    spreads: HashMap<Entity, BlightSpread>,
+}
+
+impl BlightLayer {
+    /// Clear all blight and resize to new map dimensions
+    pub fn reset(&mut self, width: i32, height: i32) {
+        self.width = width;
+        self.height = height;
+        self.cells = vec![None; (width * height) as usize];
+        self.spreads.clear();
+    }
+
+    /// Faction whose blight covers a cell
+    pub fn owner(&self, coord: GridCoord) -> Option<&FactionId> {
+        MapGrid::cell_index(self.width, self.height, coord).and_then(|index| self.cells[index].as_ref())
+    }
+
+    /// Check if any blight covers a cell
+    pub fn is_blighted(&self, coord: GridCoord) -> bool {
+        self.owner(coord).is_some()
+    }
+
+    /// Recompute cell ownership from the current spreads. Where blights overlap, the
+    /// older source keeps the cell. Blight only covers walkable ground.
+    fn rebuild(&mut self, cells: &GridCells) {
+        let mut owners = vec![None; self.cells.len()];
+
+        let mut sources: Vec<_> = self.spreads.iter().collect();
+        sources.sort_by_key(|(entity, _)| **entity);
+
+        for (_, spread) in sources {
+            for coord in cells.grid.circle(spread.center, spread.radius) {
+                let Some(index) = MapGrid::cell_index(self.width, self.height, coord) else {
+                    continue;
+                };
+                if owners[index].is_none() && cells.get(coord).is_some_and(|cell| cell.walkable) {
+                    owners[index] = Some(spread.faction.clone());
+                }
+            }
+        }
+        self.cells = owners;
+    }
+}
+
+/// Factions that can only build on their own blight
+#[derive(Resource, Debug)]
+pub struct BlightRules {
+    pub requires_blight: HashSet<FactionId>,
+}
+
+impl Default for BlightRules {
+    fn default() -> Self {
+        Self {
+            requires_blight: HashSet::from([FactionId::Creep]),
+        }
+    }
+}
+
+/// System parameter answering "can faction Y build on cell X"
+#[derive(SystemParam)]
+pub struct BuildableQuery<'w, 's> {
+    blight: Res<'w, BlightLayer>,
+    rules: Res<'w, BlightRules>,
+    cells: GridCells<'w, 's>,
+}
+
+impl BuildableQuery<'_, '_> {
+    /// Check if a faction may build on a cell. Factions that need blight can only build
+    /// on their own; everyone else is kept off blighted ground.
+    pub fn is_buildable_for(&self, faction: &FactionId, coord: GridCoord) -> bool {
+        if !self.cells.get(coord).is_some_and(|cell| cell.buildable) {
+            return false;
+        }
+        let owner = self.blight.owner(coord);
+        if self.rules.requires_blight.contains(faction) {
+            owner == Some(faction)
+        } else {
+            owner.is_none()
+        }
+    }
+}
+
+/// Blight layer plugin
+pub struct BlightPlugin;
+
+impl Plugin for BlightPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<BlightLayer>()
+            .init_resource::<BlightRules>()
+            .add_systems(Update, (
+                resize_blight,
+                track_blight_sources,
+                spread_blight,
+                render::setup_blight_overlay,
+                render::update_blight_texture,
+            ).chain().after(TerrainUpdate));
+    }
+}
+
+/// Start over whenever a new map grid is installed
+fn resize_blight(grid: Res<MapGrid>, mut blight: ResMut<BlightLayer>) {
+    if grid.is_changed() {
+        blight.reset(grid.width, grid.height);
+    }
+}
+
+/// Keep each spread in step with its source, and let it recede once the source is gone
+fn track_blight_sources(
+    grid: Res<MapGrid>,
+    mut blight: ResMut<BlightLayer>,
+    sources: Query<(Entity, &BlightSource, &Ownership, &Transform), Without<Corpse>>,
+) {
+    for spread in blight.spreads.values_mut() {
+        spread.alive = false;
+    }
+
+    for (entity, source, ownership, transform) in sources.iter() {
+        let center = grid.world_to_grid(transform.translation);
+        let spread = blight.spreads.entry(entity).or_insert_with(|| BlightSpread {
+            faction: ownership.faction.clone(),
+            center,
+            radius: 0.0,
+            max_radius: source.radius,
+            spread_rate: source.spread_rate,
+            alive: true,
+        });
+        spread.faction = ownership.faction.clone();
+        spread.center = center;
+        spread.max_radius = source.radius;
+        spread.spread_rate = source.spread_rate;
+        spread.alive = true;
+    }
+}
+
+/// Grow and shrink blight over time, then update which cells it covers
+fn spread_blight(
+    mut blight: ResMut<BlightLayer>,
+    cells: GridCells,
+    time: Res<Time>,
+) {
+    let delta = time.delta_secs();
+    let mut moved = false;
+
+    for spread in blight.spreads.values_mut() {
+        let target = if spread.alive { spread.max_radius } else { 0.0 };
+        let rate = if spread.alive { spread.spread_rate } else { RECEDE_RATE };
+        let radius = if spread.radius < target {
+            (spread.radius + rate * delta).min(target)
+        } else {
+            (spread.radius - rate * delta).max(target)
+        };
+        moved |= radius != spread.radius;
+        spread.radius = radius;
+    }
+
+    let before = blight.spreads.len();
+    blight.spreads.retain(|_, spread| spread.alive || spread.radius > 0.0);
+    if !moved && blight.spreads.len() == before {
+        return;
+    }
+
+    blight.rebuild(&cells);
+}
@@ -0,0 +1,53 @@
+use bevy::prelude::*;
+use crate::plugins::map::{CellOverlay, OverlaySpawner, OverlayStyle};
+use super::BlightLayer;
+
+/// Purple tint, nothing blighted to start with, below the fog overlay
+const BLIGHT_STYLE: OverlayStyle = OverlayStyle {
+    color: [70, 30, 80],
+    opacity: 0.0,
+    height: 0.03,
+    depth_bias: 50.0,
+};
+/// Opacity of the tint on fully blighted cells
+const BLIGHT_OPACITY: f32 = 0.6;
+/// How fast cell opacity blends towards its target, per second
+const FADE_SPEED: f32 = 3.0;
+
+/// Marker component for the blight tint mesh
+#[derive(Component)]
+pub struct BlightOverlay;
+
+/// Texture backing the blight tint
+#[derive(Resource)]
+pub struct BlightOverlayState(CellOverlay);
+
+/// Spawn a fresh tint overlay whenever a new map grid is installed
+pub fn setup_blight_overlay(mut spawner: OverlaySpawner, overlays: Query<Entity, With<BlightOverlay>>) {
+    if !spawner.grid.is_changed() && !overlays.is_empty() {
+        return;
+    }
+    let Some(overlay) = spawner.spawn(&BLIGHT_STYLE, (BlightOverlay, Name::new("Blight Overlay"))) else {
+        return;
+    };
+
+    for entity in overlays.iter() {
+        spawner.commands.entity(entity).despawn();
+    }
+    spawner.commands.insert_resource(BlightOverlayState(overlay));
+}
+
+/// Fade the tint texture towards the current blight coverage
+pub fn update_blight_texture(
+    blight: Res<BlightLayer>,
+    time: Res<Time>,
+    state: Option<ResMut<BlightOverlayState>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(mut state) = state else {
+        return;
+    };
+
+    let blend = (FADE_SPEED * time.delta_secs()).min(1.0);
+    state.0.fade(&mut images, blend, |coord| if blight.is_blighted(coord) { BLIGHT_OPACITY } else { 0.0 });
+}
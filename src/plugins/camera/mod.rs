@@ -3,11 +3,13 @@ use bevy_rts_camera::RtsCameraPlugin;
 use bevy_rts_camera::{RtsCamera, RtsCameraControls};
 
 pub mod lighting;
+pub mod picking;
 
 pub struct CameraPlugin;
 
 pub use bevy_rts_camera::Ground as CameraGround;
 use lighting::setup_lighting;
+pub use picking::CursorPick;
 
 fn add_camera_system(mut commands: Commands){
     commands.spawn((
@@ -56,6 +58,8 @@ impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app
           .add_plugins(RtsCameraPlugin)
-          .add_systems(Startup, (add_camera_system, setup_lighting));
+          .init_resource::<CursorPick>()
+          .add_systems(Startup, (add_camera_system, setup_lighting))
+          .add_systems(PreUpdate, picking::update_cursor_pick);
     }
 }
@@ -0,0 +1,153 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_rts_camera::RtsCamera;
+use crate::components::unit::Unit;
+use crate::plugins::map::{GridCoord, GridCells};
+
+/// Highest terrain elevation the ground raycast starts searching from
+const PICK_CEILING: f32 = 16.0;
+/// Distance between samples when marching the ray over the terrain, in cells
+const PICK_STEP: f32 = 0.25;
+/// Bisection steps used to refine a terrain hit
+const PICK_REFINE_STEPS: u32 = 8;
+/// Radius of the vertical capsule used to hit-test units
+const UNIT_PICK_RADIUS: f32 = 0.45;
+/// Half height of the vertical capsule segment used to hit-test units
+const UNIT_PICK_HALF_HEIGHT: f32 = 0.8;
+
+/// What the mouse cursor is currently over, updated every frame
+#[derive(Resource, Debug, Default, Clone)]
+pub struct CursorPick {
+    /// Point on the terrain under the cursor
+    pub world_point: Option<Vec3>,
+    /// Grid cell under the cursor, if inside the map
+    pub coord: Option<GridCoord>,
+    /// Unit under the cursor
+    pub unit: Option<Entity>,
+}
+
+/// Raycast from the RTS camera through the cursor onto terrain and units
+pub fn update_cursor_pick(
+    mut pick: ResMut<CursorPick>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<RtsCamera>>,
+    cells: GridCells,
+    units: Query<(Entity, &GlobalTransform, Option<&Visibility>), With<Unit>>,
+) {
+    *pick = CursorPick::default();
+
+    let Ok(window) = windows.single() else {
+        return;
+    };
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = cameras.single() else {
+        return;
+    };
+    let Ok(ray) = camera.viewport_to_world(camera_transform, cursor) else {
+        return;
+    };
+
+    let ground_hit = raycast_terrain(ray, &cells);
+    if let Some((_, point)) = ground_hit {
+        pick.world_point = Some(point);
+        let coord = cells.grid.world_to_grid(point);
+        pick.coord = cells.grid.in_bounds(coord).then_some(coord);
+    }
+
+    // Nearest visible unit in front of the ground hit
+    let max_distance = ground_hit.map_or(f32::MAX, |(distance, _)| distance);
+    pick.unit = units
+        .iter()
+        .filter(|(_, _, visibility)| visibility.is_none_or(|visibility| *visibility != Visibility::Hidden))
+        .filter_map(|(entity, transform, _)| {
+            let center = transform.translation();
+            let distance = ray_capsule_distance(
+                ray,
+                center - Vec3::Y * UNIT_PICK_HALF_HEIGHT,
+                center + Vec3::Y * UNIT_PICK_HALF_HEIGHT,
+                UNIT_PICK_RADIUS,
+            )?;
+            (distance <= max_distance).then_some((distance, entity))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, entity)| entity);
+}
+
+/// March along the ray until it drops below the cell elevation, then refine the hit.
+/// Returns the distance along the ray and the hit point.
+fn raycast_terrain(ray: Ray3d, cells: &GridCells) -> Option<(f32, Vec3)> {
+    let direction = *ray.direction;
+    if direction.y >= 0.0 {
+        return None;
+    }
+
+    let floor = ray.intersect_plane(Vec3::ZERO, InfinitePlane3d::new(Vec3::Y))?;
+    let start = ray
+        .intersect_plane(Vec3::Y * PICK_CEILING, InfinitePlane3d::new(Vec3::Y))
+        .unwrap_or(0.0);
+    let step = PICK_STEP * cells.grid.cell_size;
+
+    let below_ground = |distance: f32| {
+        let point = ray.get_point(distance);
+        point.y <= cells.grid.sample_height(point.x, point.z)
+    };
+
+    let mut previous = start;
+    let mut distance = start;
+    while distance < floor {
+        distance = (distance + step).min(floor);
+        if below_ground(distance) {
+            // Bisect between the last point above ground and this one
+            let (mut above, mut below) = (previous, distance);
+            for _ in 0..PICK_REFINE_STEPS {
+                let middle = (above + below) / 2.0;
+                if below_ground(middle) {
+                    below = middle;
+                } else {
+                    above = middle;
+                }
+            }
+            return Some((below, ray.get_point(below)));
+        }
+        previous = distance;
+    }
+
+    Some((floor, ray.get_point(floor)))
+}
+
+/// Distance along the ray to the first point within `radius` of the segment `a`-`b`
+fn ray_capsule_distance(ray: Ray3d, a: Vec3, b: Vec3, radius: f32) -> Option<f32> {
+    // Closest points between the ray and the capsule's core segment
+    let direction = *ray.direction;
+    let segment = b - a;
+    let offset = ray.origin - a;
+    let segment_length_sq = segment.length_squared();
+    let along = direction.dot(segment);
+    let offset_ray = direction.dot(offset);
+    let offset_segment = segment.dot(offset);
+    let denominator = segment_length_sq - along * along;
+
+    let mut t_segment = if denominator.abs() > f32::EPSILON {
+        ((offset_segment - along * offset_ray) / denominator).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let mut t_ray = along * t_segment - offset_ray;
+    if t_ray < 0.0 {
+        t_ray = 0.0;
+        t_segment = (offset_segment / segment_length_sq).clamp(0.0, 1.0);
+    }
+
+    let closest_ray = ray.origin + direction * t_ray;
+    let closest_segment = a + segment * t_segment;
+    let gap = closest_ray.distance(closest_segment);
+    if gap > radius {
+        return None;
+    }
+
+    // Step back from the closest approach to where the ray enters the capsule
+    let entry = (radius * radius - gap * gap).sqrt();
+    Some((t_ray - entry).max(0.0))
+}
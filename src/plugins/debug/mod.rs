@@ -0,0 +1,193 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+use crate::components::faction::LocalPlayer;
+use crate::plugins::blight::BuildableQuery;
+use crate::plugins::fog::{CellVisibility, FogOfWar};
+use crate::plugins::map::{GridCoord, GridCells, GridPosition, MapGrid, PathfindingResultEvent};
+
+/// Key that cycles through overlay modes
+const CYCLE_KEY: KeyCode = KeyCode::F3;
+/// Height of overlay lines above the cell surface
+const OVERLAY_OFFSET: f32 = 0.02;
+/// Elevation mapped to the brightest color in elevation mode
+const MAX_DISPLAY_ELEVATION: f32 = 4.0;
+
+/// What the debug overlay colors cells by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverlayMode {
+    #[default]
+    Off,
+    Borders,
+    Walkable,
+    Buildable,
+    Terrain,
+    Elevation,
+    Occupancy,
+    Fog,
+}
+
+impl OverlayMode {
+    /// The mode after this one when cycling
+    pub fn next(self) -> Self {
+        match self {
+            OverlayMode::Off => OverlayMode::Borders,
+            OverlayMode::Borders => OverlayMode::Walkable,
+            OverlayMode::Walkable => OverlayMode::Buildable,
+            OverlayMode::Buildable => OverlayMode::Terrain,
+            OverlayMode::Terrain => OverlayMode::Elevation,
+            OverlayMode::Elevation => OverlayMode::Occupancy,
+            OverlayMode::Occupancy => OverlayMode::Fog,
+            OverlayMode::Fog => OverlayMode::Off,
+        }
+    }
+}
+
+/// Resource holding the current overlay mode
+#[derive(Resource, Default)]
+pub struct DebugOverlay {
+    pub mode: OverlayMode,
+}
+
+/// Last path returned for each entity, for drawing
+#[derive(Resource, Default)]
+pub struct DebugPaths {
+    pub paths: HashMap<Entity, Vec<GridCoord>>,
+}
+
+/// Debug overlay plugin
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<DebugOverlay>()
+            .init_resource::<DebugPaths>()
+            .add_systems(Update, (
+                cycle_overlay_mode,
+                record_paths,
+                draw_cell_overlay,
+                draw_paths,
+            ).chain());
+    }
+}
+
+/// Cycle overlay modes with the keybinding
+fn cycle_overlay_mode(keys: Res<ButtonInput<KeyCode>>, mut overlay: ResMut<DebugOverlay>) {
+    if keys.just_pressed(CYCLE_KEY) {
+        overlay.mode = overlay.mode.next();
+        info!("Debug overlay: {:?}", overlay.mode);
+    }
+}
+
+/// Remember the most recent path found for each entity
+fn record_paths(mut events: EventReader<PathfindingResultEvent>, mut paths: ResMut<DebugPaths>) {
+    for event in events.read() {
+        if event.success {
+            paths.paths.insert(event.entity, event.path.clone());
+        } else {
+            paths.paths.remove(&event.entity);
+        }
+    }
+}
+
+/// Draw cell borders and per-cell colors for the active mode
+fn draw_cell_overlay(
+    overlay: Res<DebugOverlay>,
+    cells: GridCells,
+    occupants: Query<&GridPosition>,
+    fog: Res<FogOfWar>,
+    local_player: Res<LocalPlayer>,
+    buildable: BuildableQuery,
+    mut gizmos: Gizmos,
+) {
+    if overlay.mode == OverlayMode::Off {
+        return;
+    }
+
+    let grid = &cells.grid;
+    let border_color = Color::srgba(1.0, 1.0, 1.0, 0.15);
+
+    let mut occupancy: HashMap<GridCoord, usize> = HashMap::new();
+    if overlay.mode == OverlayMode::Occupancy {
+        for position in occupants.iter() {
+            *occupancy.entry(position.0).or_default() += 1;
+        }
+    }
+
+    for x in 0..grid.width {
+        for y in 0..grid.height {
+            let coord = GridCoord { x, y };
+            let Some(cell) = cells.get(coord) else {
+                continue;
+            };
+            let height = grid.cell_height(coord) + OVERLAY_OFFSET;
+            outline_cell(&mut gizmos, grid, coord, height, 1.0, border_color);
+
+            let color = match overlay.mode {
+                OverlayMode::Off | OverlayMode::Borders => None,
+                OverlayMode::Walkable => Some(pass_fail_color(cell.walkable)),
+                OverlayMode::Buildable => Some(pass_fail_color(buildable.is_buildable_for(&local_player.faction, coord))),
+                OverlayMode::Terrain => Some(cells.terrains.get(&cell.terrain).color()),
+                OverlayMode::Elevation if cell.ramp => Some(Color::srgb(1.0, 0.8, 0.1)),
+                OverlayMode::Elevation => {
+                    let t = (cell.height() / MAX_DISPLAY_ELEVATION).clamp(0.0, 1.0);
+                    Some(Color::srgb(0.1, 0.1, 0.4).mix(&Color::WHITE, t))
+                }
+                OverlayMode::Occupancy => match occupancy.get(&coord).copied().unwrap_or(0) {
+                    0 => None,
+                    1 => Some(Color::srgb(1.0, 0.8, 0.1)),
+                    _ => Some(Color::srgb(1.0, 0.2, 0.1)),
+                },
+                OverlayMode::Fog => Some(match fog.state(&local_player.faction, coord) {
+                    CellVisibility::Unexplored => Color::BLACK,
+                    CellVisibility::Explored => Color::srgb(0.4, 0.4, 0.4),
+                    CellVisibility::Visible => Color::WHITE,
+                }),
+            };
+
+            if let Some(color) = color {
+                outline_cell(&mut gizmos, grid, coord, height, 0.8, color);
+            }
+        }
+    }
+}
+
+/// Outline a cell in its own shape, square or hex, shrunk around its center by `scale`
+fn outline_cell(gizmos: &mut Gizmos, grid: &MapGrid, coord: GridCoord, height: f32, scale: f32, color: Color) {
+    let center = grid.grid_to_world_at(coord, height);
+    let corners: Vec<Vec3> = grid
+        .cell_corners(coord)
+        .into_iter()
+        .map(|corner| center.lerp(Vec3::new(corner.x, height, corner.y), scale))
+        .collect();
+    // Close the loop back at the first corner
+    gizmos.linestrip(corners.iter().chain(corners.first()).copied(), color);
+}
+
+/// Draw the last known path of every entity
+fn draw_paths(
+    overlay: Res<DebugOverlay>,
+    paths: Res<DebugPaths>,
+    grid: Res<MapGrid>,
+    mut gizmos: Gizmos,
+) {
+    if overlay.mode == OverlayMode::Off {
+        return;
+    }
+
+    let path_color = Color::srgb(0.2, 0.9, 1.0);
+    for path in paths.paths.values() {
+        let points = path
+            .iter()
+            .map(|coord| grid.grid_to_world(*coord) + Vec3::Y * OVERLAY_OFFSET * 2.0);
+        gizmos.linestrip(points, path_color);
+    }
+}
+
+fn pass_fail_color(pass: bool) -> Color {
+    if pass {
+        Color::srgb(0.2, 0.9, 0.2)
+    } else {
+        Color::srgb(0.9, 0.2, 0.2)
+    }
+}
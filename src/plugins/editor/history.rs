@@ -0,0 +1,134 @@
+use bevy::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use crate::plugins::map::{GridCoord, GridCell, GridCells, TerrainModifiedEvent};
+use super::BrushStroke;
+
+/// Maximum number of strokes kept for undo
+const MAX_HISTORY: usize = 100;
+
+/// Before and after state of every cell touched by one brush stroke
+#[derive(Debug, Clone, Default)]
+pub struct HistoryEntry {
+    pub before: HashMap<GridCoord, GridCell>,
+    pub after: HashMap<GridCoord, GridCell>,
+}
+
+/// Bounded undo/redo stacks of terrain edits
+#[derive(Resource, Debug, Default)]
+pub struct EditHistory {
+    undo: VecDeque<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    /// Stroke currently being recorded
+    pending: Option<HistoryEntry>,
+}
+
+impl EditHistory {
+    /// Record a new edit, which invalidates anything that could be redone
+    pub fn push(&mut self, entry: HistoryEntry) {
+        self.push_undo(entry);
+        self.redo.clear();
+    }
+
+    /// Push onto the undo stack, dropping the oldest entry once the history is full
+    fn push_undo(&mut self, entry: HistoryEntry) {
+        if self.undo.len() == MAX_HISTORY {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(entry);
+    }
+
+    /// Forget all recorded edits
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.pending = None;
+    }
+}
+
+/// Capture the state of cells before a stroke's edits are applied to them.
+/// Runs after the brush and before the map applies terrain modifications.
+pub fn record_stroke_edits(
+    mut events: EventReader<TerrainModifiedEvent>,
+    stroke: Res<BrushStroke>,
+    mut history: ResMut<EditHistory>,
+    cells: GridCells,
+) {
+    if !stroke.active {
+        // Edits outside of a stroke (undo, redo, scripts) are not recorded
+        events.clear();
+        return;
+    }
+
+    let pending = history.pending.get_or_insert_with(HistoryEntry::default);
+    for event in events.read() {
+        if pending.before.contains_key(&event.coord) {
+            continue;
+        }
+        if let Some(cell) = cells.get(event.coord) {
+            pending.before.insert(event.coord, cell.clone());
+        }
+    }
+}
+
+/// Close the pending entry once the stroke ends, reading the edited cells back.
+/// Runs after the map has applied terrain modifications.
+pub fn finish_stroke(
+    stroke: Res<BrushStroke>,
+    mut history: ResMut<EditHistory>,
+    cells: GridCells,
+) {
+    if stroke.active {
+        return;
+    }
+    let Some(mut entry) = history.pending.take() else {
+        return;
+    };
+    if entry.before.is_empty() {
+        return;
+    }
+
+    for coord in entry.before.keys() {
+        if let Some(cell) = cells.get(*coord) {
+            entry.after.insert(*coord, cell.clone());
+        }
+    }
+    history.push(entry);
+}
+
+/// Undo with Ctrl+Z, redo with Ctrl+Y or Ctrl+Shift+Z
+pub fn undo_redo(
+    keys: Res<ButtonInput<KeyCode>>,
+    stroke: Res<BrushStroke>,
+    mut history: ResMut<EditHistory>,
+    time: Res<Time>,
+    mut terrain_events: EventWriter<TerrainModifiedEvent>,
+) {
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if !ctrl || stroke.active {
+        return;
+    }
+
+    let undo = keys.just_pressed(KeyCode::KeyZ) && !shift;
+    let redo = keys.just_pressed(KeyCode::KeyY) || (keys.just_pressed(KeyCode::KeyZ) && shift);
+    let timestamp = time.elapsed_secs_f64();
+
+    if undo && let Some(entry) = history.undo.pop_back() {
+        for (coord, cell) in &entry.before {
+            terrain_events.write(TerrainModifiedEvent::set_cell(*coord, cell, timestamp));
+        }
+        history.redo.push(entry);
+    } else if redo && !undo && let Some(entry) = history.redo.pop() {
+        for (coord, cell) in &entry.after {
+            terrain_events.write(TerrainModifiedEvent::set_cell(*coord, cell, timestamp));
+        }
+        history.push_undo(entry);
+    }
+}
+
+/// Start with an empty history on every new map
+pub fn reset_history(cells: GridCells, mut history: ResMut<EditHistory>) {
+    if cells.grid.is_changed() {
+        history.clear();
+    }
+}
@@ -0,0 +1,419 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use std::f32::consts::FRAC_PI_2;
+use crate::components::faction::FactionId;
+use crate::plugins::camera::CursorPick;
+use crate::plugins::triggers::TriggerSet;
+use crate::plugins::map::{
+    CellStore, GridCoord, GridCell, GridCells, LoadedMap, MapGrid, MapFile, MapObjects, RegionRegistry, ResourceKind,
+    ResourceNode, StartLocation, TerrainModifiedEvent, TerrainRegistry, TerrainType, TerrainUpdate,
+    MAX_CLIFF_LEVEL,
+};
+
+pub mod history;
+
+use history::EditHistory;
+
+/// Key that switches editor mode on and off
+const TOGGLE_KEY: KeyCode = KeyCode::F2;
+/// Elevation change per second while raising or lowering
+const ELEVATION_RATE: f32 = 2.0;
+/// Largest brush radius in cells
+const MAX_BRUSH_SIZE: i32 = 8;
+/// Amount placed in a new gold mine
+const DEFAULT_GOLD: u32 = 12500;
+/// Amount placed in a new lumber node
+const DEFAULT_LUMBER: u32 = 5000;
+
+/// Whether the map editor is active
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EditorState {
+    #[default]
+    Disabled,
+    Enabled,
+}
+
+/// What a brush stroke does to the cells under it
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditorTool {
+    Paint(TerrainType),
+    Raise,
+    Lower,
+    /// Set cells one cliff level above the level the stroke started on
+    CliffRaise,
+    /// Set cells one cliff level below the level the stroke started on
+    CliffLower,
+    ToggleWalkable,
+    ToggleBuildable,
+    ToggleRamp,
+    StartLocation,
+    Resource(ResourceKind),
+}
+
+/// Footprint of the brush
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrushShape {
+    Round,
+    Square,
+}
+
+/// Current editor tool and brush settings
+#[derive(Resource, Debug, Clone)]
+pub struct EditorBrush {
+    pub tool: EditorTool,
+    pub shape: BrushShape,
+    /// Brush radius in cells, 0 paints a single cell
+    pub size: i32,
+}
+
+impl Default for EditorBrush {
+    fn default() -> Self {
+        Self {
+            tool: EditorTool::Paint(TerrainType::default()),
+            shape: BrushShape::Round,
+            size: 1,
+        }
+    }
+}
+
+impl EditorBrush {
+    /// Cells on the map covered by the brush centered on `center`
+    pub fn cells(&self, grid: &MapGrid, center: GridCoord) -> Vec<GridCoord> {
+        match self.shape {
+            BrushShape::Round => grid.circle(center, self.size as f32).collect(),
+            BrushShape::Square => grid
+                .rect(center.offset(-self.size, -self.size), center.offset(self.size, self.size))
+                .collect(),
+        }
+    }
+}
+
+/// State of the brush stroke in progress, from mouse press to release
+#[derive(Resource, Debug, Default)]
+pub struct BrushStroke {
+    pub active: bool,
+    /// Last cell the brush was applied at, so dragging only paints new cells
+    last_center: Option<GridCoord>,
+    /// Value walkable/buildable/ramp toggles set for the whole stroke
+    toggle_value: Option<bool>,
+    /// Cliff level the cliff tools set for the whole stroke
+    cliff_level: Option<i32>,
+}
+
+/// System parameter with the mouse state the brush follows
+#[derive(SystemParam)]
+struct BrushInput<'w> {
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    pick: Res<'w, CursorPick>,
+}
+
+/// System parameter with everything that goes into the edited map's file
+#[derive(SystemParam)]
+struct EditedMap<'w> {
+    grid: Res<'w, MapGrid>,
+    store: Res<'w, CellStore>,
+    terrains: Res<'w, TerrainRegistry>,
+    objects: Res<'w, MapObjects>,
+    regions: Res<'w, RegionRegistry>,
+    triggers: Res<'w, TriggerSet>,
+    loaded_map: Res<'w, LoadedMap>,
+}
+
+impl EditedMap<'_> {
+    /// The map as it would be saved right now
+    fn to_map_file(&self) -> MapFile {
+        MapFile {
+            name: self.loaded_map.name.clone(),
+            width: self.grid.width,
+            height: self.grid.height,
+            cell_size: self.grid.cell_size,
+            topology: self.grid.topology,
+            // The store holds every cell, including those in unloaded chunks
+            cells: self.store.cells().to_vec(),
+            start_locations: self.objects.start_locations.clone(),
+            resources: self.objects.resources.clone(),
+            regions: self.regions.iter().cloned().collect(),
+            triggers: self.triggers.definitions(),
+            terrains: self.terrains.map_terrains().to_vec(),
+        }
+    }
+}
+
+/// Map editor plugin
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_state::<EditorState>()
+            .init_resource::<EditorBrush>()
+            .init_resource::<BrushStroke>()
+            .init_resource::<EditHistory>()
+            .add_systems(Update, (toggle_editor, history::reset_history))
+            // Edits are recorded before the map applies them, and closed off after
+            .add_systems(Update, (
+                select_tool,
+                history::undo_redo,
+                apply_brush,
+                history::record_stroke_edits,
+            ).chain().before(TerrainUpdate).run_if(in_state(EditorState::Enabled)))
+            .add_systems(Update, (
+                history::finish_stroke,
+                save_map,
+                draw_editor_gizmos,
+            ).chain().after(TerrainUpdate).run_if(in_state(EditorState::Enabled)));
+    }
+}
+
+/// Switch editor mode on and off
+fn toggle_editor(
+    keys: Res<ButtonInput<KeyCode>>,
+    state: Res<State<EditorState>>,
+    mut next_state: ResMut<NextState<EditorState>>,
+) {
+    if keys.just_pressed(TOGGLE_KEY) {
+        let next = match state.get() {
+            EditorState::Disabled => EditorState::Enabled,
+            EditorState::Enabled => EditorState::Disabled,
+        };
+        info!("Editor: {:?}", next);
+        next_state.set(next);
+    }
+}
+
+/// Keyboard shortcuts for tools and brush settings
+fn select_tool(
+    keys: Res<ButtonInput<KeyCode>>,
+    terrains: Res<TerrainRegistry>,
+    mut brush: ResMut<EditorBrush>,
+) {
+    // Ctrl combinations are editor commands, not tool shortcuts
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+
+    // Number keys pick terrains in the order they are defined
+    let terrain_keys = [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+    ];
+    let tool_keys = [
+        (KeyCode::KeyR, EditorTool::Raise),
+        (KeyCode::KeyF, EditorTool::Lower),
+        (KeyCode::KeyC, EditorTool::CliffRaise),
+        (KeyCode::KeyV, EditorTool::CliffLower),
+        (KeyCode::KeyN, EditorTool::ToggleRamp),
+        (KeyCode::KeyZ, EditorTool::ToggleWalkable),
+        (KeyCode::KeyX, EditorTool::ToggleBuildable),
+        (KeyCode::KeyT, EditorTool::StartLocation),
+        (KeyCode::KeyG, EditorTool::Resource(ResourceKind::Gold)),
+        (KeyCode::KeyL, EditorTool::Resource(ResourceKind::Lumber)),
+    ];
+
+    let mut changed = false;
+    for (key, terrain) in terrain_keys.into_iter().zip(terrains.iter()) {
+        if keys.just_pressed(key) {
+            brush.tool = EditorTool::Paint(terrain.id.clone());
+            changed = true;
+        }
+    }
+    for (key, tool) in tool_keys {
+        if keys.just_pressed(key) {
+            brush.tool = tool;
+            changed = true;
+        }
+    }
+    if keys.just_pressed(KeyCode::KeyB) {
+        brush.shape = match brush.shape {
+            BrushShape::Round => BrushShape::Square,
+            BrushShape::Square => BrushShape::Round,
+        };
+        changed = true;
+    }
+    if keys.just_pressed(KeyCode::BracketLeft) {
+        brush.size = (brush.size - 1).max(0);
+        changed = true;
+    }
+    if keys.just_pressed(KeyCode::BracketRight) {
+        brush.size = (brush.size + 1).min(MAX_BRUSH_SIZE);
+        changed = true;
+    }
+
+    if changed {
+        info!("Brush: {:?} {:?} size {}", brush.tool, brush.shape, brush.size);
+    }
+}
+
+/// Apply the current tool under the cursor while the left mouse button is held
+fn apply_brush(
+    input: BrushInput,
+    brush: Res<EditorBrush>,
+    mut stroke: ResMut<BrushStroke>,
+    cells: GridCells,
+    mut objects: ResMut<MapObjects>,
+    time: Res<Time>,
+    mut terrain_events: EventWriter<TerrainModifiedEvent>,
+) {
+    if !input.mouse.pressed(MouseButton::Left) {
+        *stroke = BrushStroke::default();
+        return;
+    }
+    let Some(center) = input.pick.coord else {
+        return;
+    };
+
+    let starting = !stroke.active;
+    let entered_cell = stroke.last_center != Some(center);
+    stroke.active = true;
+    stroke.last_center = Some(center);
+
+    let timestamp = time.elapsed_secs_f64();
+    let targets = brush.cells(&cells.grid, center);
+
+    match &brush.tool {
+        EditorTool::Paint(terrain) => {
+            if !entered_cell {
+                return;
+            }
+            for coord in targets {
+                if cells.get(coord).is_some_and(|cell| &cell.terrain != terrain) {
+                    terrain_events.write(TerrainModifiedEvent::terrain(coord, terrain.clone(), timestamp));
+                }
+            }
+        }
+        EditorTool::Raise | EditorTool::Lower => {
+            let direction = if brush.tool == EditorTool::Raise { 1.0 } else { -1.0 };
+            let delta = direction * ELEVATION_RATE * time.delta_secs();
+            for coord in targets {
+                let Some(mut cell) = cells.get(coord).cloned() else {
+                    continue;
+                };
+                cell.elevation += delta;
+                terrain_events.write(TerrainModifiedEvent::set_cell(coord, &cell, timestamp));
+            }
+        }
+        EditorTool::CliffRaise | EditorTool::CliffLower => {
+            if !entered_cell {
+                return;
+            }
+            let step = if brush.tool == EditorTool::CliffRaise { 1 } else { -1 };
+            // The first cell decides the level this stroke flattens everything to
+            let level = *stroke.cliff_level.get_or_insert_with(|| {
+                let current = cells.get(center).map_or(0, |cell| cell.cliff_level);
+                (current + step).clamp(0, MAX_CLIFF_LEVEL)
+            });
+            for coord in targets {
+                let Some(mut cell) = cells.get(coord).cloned() else {
+                    continue;
+                };
+                if cell.cliff_level != level {
+                    cell.cliff_level = level;
+                    terrain_events.write(TerrainModifiedEvent::set_cell(coord, &cell, timestamp));
+                }
+            }
+        }
+        EditorTool::ToggleWalkable | EditorTool::ToggleBuildable | EditorTool::ToggleRamp => {
+            if !entered_cell {
+                return;
+            }
+            // The first cell decides whether this stroke turns the flag on or off
+            let value = *stroke.toggle_value.get_or_insert_with(|| {
+                !cells.get(center).cloned().is_some_and(|mut cell| *toggled_flag(&brush.tool, &mut cell))
+            });
+            for coord in targets {
+                let Some(mut cell) = cells.get(coord).cloned() else {
+                    continue;
+                };
+                *toggled_flag(&brush.tool, &mut cell) = value;
+                terrain_events.write(TerrainModifiedEvent::set_cell(coord, &cell, timestamp));
+            }
+        }
+        EditorTool::StartLocation => {
+            if !starting {
+                return;
+            }
+            // Clicking an existing start location removes it
+            if let Some(index) = objects.start_locations.iter().position(|start| start.coord == center) {
+                objects.start_locations.remove(index);
+                return;
+            }
+            let player = (1..)
+                .find(|player| !objects.start_locations.iter().any(|start| start.player == *player))
+                .unwrap_or(1);
+            objects.start_locations.push(StartLocation { player, coord: center });
+        }
+        EditorTool::Resource(kind) => {
+            if !starting {
+                return;
+            }
+            // Clicking an existing resource node removes it
+            if let Some(index) = objects.resources.iter().position(|node| node.coord == center) {
+                objects.resources.remove(index);
+                return;
+            }
+            let amount = match kind {
+                ResourceKind::Gold => DEFAULT_GOLD,
+                ResourceKind::Lumber => DEFAULT_LUMBER,
+            };
+            objects.resources.push(ResourceNode { kind: *kind, coord: center, amount });
+        }
+    }
+}
+
+/// Save the edited map with Ctrl+S
+fn save_map(keys: Res<ButtonInput<KeyCode>>, edited: EditedMap) {
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    if !ctrl || !keys.just_pressed(KeyCode::KeyS) {
+        return;
+    }
+
+    match edited.to_map_file().save() {
+        Ok(path) => info!("Saved map to {}", path.display()),
+        Err(error) => error!("Failed to save map: {error}"),
+    }
+}
+
+/// Cell flag a toggle tool flips
+fn toggled_flag<'a>(tool: &EditorTool, cell: &'a mut GridCell) -> &'a mut bool {
+    match tool {
+        EditorTool::ToggleWalkable => &mut cell.walkable,
+        EditorTool::ToggleBuildable => &mut cell.buildable,
+        _ => &mut cell.ramp,
+    }
+}
+
+/// Draw the brush outline and placed map objects
+fn draw_editor_gizmos(
+    pick: Res<CursorPick>,
+    brush: Res<EditorBrush>,
+    grid: Res<MapGrid>,
+    objects: Res<MapObjects>,
+    mut gizmos: Gizmos,
+) {
+    let flat = Quat::from_rotation_x(-FRAC_PI_2);
+    let surface = |coord: GridCoord| grid.grid_to_world(coord) + Vec3::Y * 0.05;
+
+    if let Some(center) = pick.coord {
+        for coord in brush.cells(&grid, center) {
+            gizmos.rect(Isometry3d::new(surface(coord), flat), Vec2::splat(grid.cell_size), Color::WHITE);
+        }
+    }
+
+    for start in &objects.start_locations {
+        let color = FactionId::Player(start.player).color();
+        gizmos.circle(Isometry3d::new(surface(start.coord), flat), grid.cell_size * 0.8, color);
+    }
+
+    for node in &objects.resources {
+        let color = node.kind.color();
+        let rotation = flat * Quat::from_rotation_z(FRAC_PI_2 / 2.0);
+        gizmos.rect(Isometry3d::new(surface(node.coord), rotation), Vec2::splat(grid.cell_size * 0.6), color);
+    }
+}
@@ -0,0 +1,209 @@
+use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
+use std::collections::HashMap;
+use crate::components::unit::{Unit, UnitState, Statsheet};
+use crate::components::faction::{Ownership, FactionId, LocalPlayer};
+use crate::plugins::map::{GridCoord, GridCells, MapGrid, TerrainRevealedEvent};
+
+pub mod render;
+
+/// System set updating fog of war state, rendering runs after it
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FogUpdate;
+
+/// Visibility state of a single cell for one faction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CellVisibility {
+    #[default]
+    Unexplored,
+    Explored,
+    Visible,
+}
+
+/// Event for when a reveal uncovers cells a faction had never seen before. Sight is
+/// revealed every tick, so this is what fires once as ground gets explored.
+#[derive(Event)]
+pub struct TerrainExploredEvent {
+    pub center: GridCoord,
+    pub faction: FactionId,
+}
+
+/// Per-faction grid of cell visibility
+#[derive(Debug, Clone)]
+pub struct VisionLayer {
+    cells: Vec<CellVisibility>,
+}
+
+/// Resource holding the fog of war state for every faction that has vision
+#[derive(Resource, Default)]
+pub struct FogOfWar {
+    width: i32,
+    height: i32,
+    layers: HashMap<FactionId, VisionLayer>,
+}
+
+impl FogOfWar {
+    /// Forget all vision and resize to new map dimensions
+    pub fn reset(&mut self, width: i32, height: i32) {
+        self.width = width;
+        self.height = height;
+        self.layers.clear();
+    }
+
+    /// Visibility of a cell for a faction
+    pub fn state(&self, faction: &FactionId, coord: GridCoord) -> CellVisibility {
+        match (self.layers.get(faction), MapGrid::cell_index(self.width, self.height, coord)) {
+            (Some(layer), Some(index)) => layer.cells[index],
+            _ => CellVisibility::Unexplored,
+        }
+    }
+
+    /// Check if a faction currently sees a cell
+    pub fn is_visible(&self, faction: &FactionId, coord: GridCoord) -> bool {
+        self.state(faction, coord) == CellVisibility::Visible
+    }
+
+    /// Mark a cell as currently visible for a faction, returning whether it had never
+    /// been seen before
+    pub fn reveal(&mut self, faction: &FactionId, coord: GridCoord) -> bool {
+        let Some(index) = MapGrid::cell_index(self.width, self.height, coord) else {
+            return false;
+        };
+        let size = (self.width * self.height) as usize;
+        let layer = self.layers.entry(faction.clone()).or_insert_with(|| VisionLayer {
+            cells: vec![CellVisibility::Unexplored; size],
+        });
+        let unexplored = layer.cells[index] == CellVisibility::Unexplored;
+        layer.cells[index] = CellVisibility::Visible;
+        unexplored
+    }
+
+    /// Downgrade everything currently visible to explored, ready for a new tick
+    fn begin_tick(&mut self) {
+        for layer in self.layers.values_mut() {
+            for cell in layer.cells.iter_mut() {
+                if *cell == CellVisibility::Visible {
+                    *cell = CellVisibility::Explored;
+                }
+            }
+        }
+    }
+}
+
+/// System parameter answering "can faction Y see entity X"
+#[derive(SystemParam)]
+pub struct FogQuery<'w, 's> {
+    fog: Res<'w, FogOfWar>,
+    grid: Res<'w, MapGrid>,
+    local_player: Res<'w, LocalPlayer>,
+    targets: Query<'w, 's, (&'static Transform, Option<&'static Ownership>)>,
+}
+
+impl FogQuery<'_, '_> {
+    /// Check if an entity is visible to a faction; factions always see their own entities
+    pub fn is_entity_visible(&self, entity: Entity, faction: &FactionId) -> bool {
+        let Ok((transform, ownership)) = self.targets.get(entity) else {
+            return false;
+        };
+        if ownership.is_some_and(|ownership| ownership.faction == *faction) {
+            return true;
+        }
+        self.fog.is_visible(faction, self.grid.world_to_grid(transform.translation))
+    }
+
+    /// Check if an entity is visible to the local player, who always sees their allies' entities
+    pub fn is_visible_to_local_player(&self, entity: Entity) -> bool {
+        let friendly = self
+            .targets
+            .get(entity)
+            .is_ok_and(|(_, ownership)| ownership.is_some_and(|ownership| self.local_player.is_friendly(ownership)));
+        friendly || self.is_entity_visible(entity, &self.local_player.faction)
+    }
+
+    /// Visibility of a cell for the local player
+    pub fn local_cell_state(&self, coord: GridCoord) -> CellVisibility {
+        self.fog.state(&self.local_player.faction, coord)
+    }
+}
+
+/// Fog of war plugin
+pub struct FogOfWarPlugin;
+
+impl Plugin for FogOfWarPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<TerrainExploredEvent>()
+            .init_resource::<FogOfWar>()
+            .init_resource::<LocalPlayer>()
+            .add_systems(Update, (
+                resize_fog,
+                begin_vision_tick,
+                emit_unit_vision,
+                apply_revealed_terrain,
+            ).chain().in_set(FogUpdate))
+            .add_systems(Update, (
+                render::setup_fog_overlay,
+                render::update_fog_texture,
+                render::update_unit_fog_visibility,
+                render::cleanup_fog_ghosts,
+            ).chain().after(FogUpdate));
+    }
+}
+
+/// Reset the fog whenever a new map grid is installed
+fn resize_fog(grid: Res<MapGrid>, mut fog: ResMut<FogOfWar>) {
+    if grid.is_changed() {
+        fog.reset(grid.width, grid.height);
+    }
+}
+
+/// Clear last tick's vision so only what is seen this tick stays visible
+fn begin_vision_tick(mut fog: ResMut<FogOfWar>) {
+    fog.begin_tick();
+}
+
+/// Every living unit reveals the terrain around it for its faction
+fn emit_unit_vision(
+    units: Query<(&Unit, &Statsheet, &Ownership, &Transform)>,
+    grid: Res<MapGrid>,
+    time: Res<Time>,
+    mut reveal_events: EventWriter<TerrainRevealedEvent>,
+) {
+    for (unit, stats, ownership, transform) in units.iter() {
+        if unit.state == UnitState::Dead {
+            continue;
+        }
+
+        reveal_events.write(TerrainRevealedEvent {
+            center: grid.world_to_grid(transform.translation),
+            radius: (stats.sight_range / grid.cell_size).ceil() as i32,
+            faction: ownership.faction.clone(),
+            line_of_sight: true,
+            timestamp: time.elapsed_secs_f64(),
+        });
+    }
+}
+
+/// Apply reveal events to the fog grid, announcing those that explore new ground
+fn apply_revealed_terrain(
+    mut events: EventReader<TerrainRevealedEvent>,
+    mut fog: ResMut<FogOfWar>,
+    cells: GridCells,
+    mut explored_events: EventWriter<TerrainExploredEvent>,
+) {
+    for event in events.read() {
+        let mut explored = false;
+        for coord in cells.grid.circle(event.center, event.radius as f32) {
+            if event.line_of_sight && !cells.has_line_of_sight(event.center, coord) {
+                continue;
+            }
+            explored |= fog.reveal(&event.faction, coord);
+        }
+        if explored {
+            explored_events.write(TerrainExploredEvent {
+                center: event.center,
+                faction: event.faction.clone(),
+            });
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use bevy::prelude::*;
+use crate::components::unit::{Unit, UnitType};
+use crate::components::faction::LocalPlayer;
+use crate::plugins::map::{CellOverlay, Dormant, GridCoord, MapGrid, OverlaySpawner, OverlayStyle};
+use super::{CellVisibility, FogOfWar, FogQuery};
+
+/// Black overlay, fully fogged to start with, above the blight tint
+const FOG_STYLE: OverlayStyle = OverlayStyle {
+    color: [0, 0, 0],
+    opacity: 1.0,
+    height: 0.05,
+    depth_bias: 100.0,
+};
+/// Overlay opacity for explored cells that are not currently visible
+const EXPLORED_OPACITY: f32 = 0.55;
+/// How fast cell opacity blends towards its target, per second
+const FADE_SPEED: f32 = 6.0;
+
+/// Marker component for the fog overlay mesh
+#[derive(Component)]
+pub struct FogOverlay;
+
+/// Overlay texture and ghost material of the fog
+#[derive(Resource)]
+pub struct FogOverlayState {
+    overlay: CellOverlay,
+    ghost_material: Handle<StandardMaterial>,
+}
+
+/// Placeholder left behind where the local player last saw an enemy building
+#[derive(Component)]
+pub struct FogGhost {
+    pub source: Entity,
+    pub coord: GridCoord,
+}
+
+/// Marks a building that the local player has seen at least once
+#[derive(Component)]
+pub struct SeenByLocalPlayer;
+
+/// Links a building to the ghost currently standing in for it
+#[derive(Component)]
+pub struct Ghosted(pub Entity);
+
+/// Spawn a fresh overlay whenever a new map grid is installed
+pub fn setup_fog_overlay(mut spawner: OverlaySpawner, overlays: Query<Entity, With<FogOverlay>>) {
+    if !spawner.grid.is_changed() && !overlays.is_empty() {
+        return;
+    }
+    let Some(overlay) = spawner.spawn(&FOG_STYLE, (FogOverlay, Name::new("Fog Overlay"))) else {
+        return;
+    };
+
+    for entity in overlays.iter() {
+        spawner.commands.entity(entity).despawn();
+    }
+
+    let ghost_material = spawner.materials.add(StandardMaterial {
+        base_color: Color::srgba(0.5, 0.5, 0.5, 0.6),
+        alpha_mode: AlphaMode::Blend,
+        ..default()
+    });
+
+    spawner.commands.insert_resource(FogOverlayState { overlay, ghost_material });
+}
+
+/// Fade the overlay texture towards the local player's current visibility
+pub fn update_fog_texture(
+    fog: Res<FogOfWar>,
+    local_player: Res<LocalPlayer>,
+    time: Res<Time>,
+    state: Option<ResMut<FogOverlayState>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(mut state) = state else {
+        return;
+    };
+
+    let blend = (FADE_SPEED * time.delta_secs()).min(1.0);
+    state.overlay.fade(&mut images, blend, |coord| match fog.state(&local_player.faction, coord) {
+        CellVisibility::Unexplored => 1.0,
+        CellVisibility::Explored => EXPLORED_OPACITY,
+        CellVisibility::Visible => 0.0,
+    });
+}
+
+/// What fog rendering needs to know about a unit
+type FoggedUnit<'a> = (
+    Entity,
+    &'a Unit,
+    &'a Transform,
+    &'a mut Visibility,
+    Option<&'a Mesh3d>,
+    Option<&'a Ghosted>,
+    Has<SeenByLocalPlayer>,
+);
+
+/// Hide enemy units outside vision and leave ghosts of enemy buildings seen earlier
+pub fn update_unit_fog_visibility(
+    mut commands: Commands,
+    fog: FogQuery,
+    grid: Res<MapGrid>,
+    state: Option<Res<FogOverlayState>>,
+    mut units: Query<FoggedUnit, Without<Dormant>>,
+) {
+    let Some(state) = state else {
+        return;
+    };
+
+    for (entity, unit, transform, mut visibility, mesh, ghosted, seen) in units.iter_mut() {
+        let coord = grid.world_to_grid(transform.translation);
+        let visible = fog.is_visible_to_local_player(entity);
+        visibility.set_if_neq(if visible { Visibility::Inherited } else { Visibility::Hidden });
+
+        if unit.unit_type != UnitType::Building {
+            continue;
+        }
+
+        if visible {
+            if !seen {
+                commands.entity(entity).insert(SeenByLocalPlayer);
+            }
+            if let Some(ghosted) = ghosted {
+                commands.entity(ghosted.0).despawn();
+                commands.entity(entity).remove::<Ghosted>();
+            }
+        } else if seen && ghosted.is_none() {
+            let Some(mesh) = mesh else {
+                continue;
+            };
+            let ghost = commands.spawn((
+                Mesh3d(mesh.0.clone()),
+                MeshMaterial3d(state.ghost_material.clone()),
+                *transform,
+                FogGhost { source: entity, coord },
+            )).id();
+            commands.entity(entity).insert(Ghosted(ghost));
+        }
+    }
+}
+
+/// Remove ghosts of buildings that have since been destroyed once their cell is seen again
+pub fn cleanup_fog_ghosts(
+    mut commands: Commands,
+    fog: Res<FogOfWar>,
+    local_player: Res<LocalPlayer>,
+    ghosts: Query<(Entity, &FogGhost)>,
+    sources: Query<(), With<Unit>>,
+) {
+    for (entity, ghost) in ghosts.iter() {
+        if sources.contains(ghost.source) {
+            continue;
+        }
+        if fog.is_visible(&local_player.faction, ghost.coord) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use bevy::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use super::grid::{GridCoord, GridCell, MapGrid};
+use super::pathfinding::levels_connected;
+
+/// Width and height of a streaming chunk in cells
+pub const CHUNK_SIZE: i32 = 16;
+
+/// Chunk neighbor directions, in the order of `ChunkSummary::open_edges`
+const CHUNK_DIRECTIONS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+/// Position of a chunk, in chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkCoord {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl ChunkCoord {
+    /// Chunk containing a cell
+    pub fn of(coord: GridCoord) -> Self {
+        Self {
+            x: coord.x.div_euclid(CHUNK_SIZE),
+            y: coord.y.div_euclid(CHUNK_SIZE),
+        }
+    }
+
+    /// Cell at the chunk's minimum corner
+    pub fn origin(self) -> GridCoord {
+        GridCoord { x: self.x * CHUNK_SIZE, y: self.y * CHUNK_SIZE }
+    }
+
+    /// Every cell of the chunk, whether or not it is on the map
+    pub fn cells(self) -> impl Iterator<Item = GridCoord> {
+        self.origin().footprint(CHUNK_SIZE, CHUNK_SIZE)
+    }
+
+    pub fn chebyshev_distance(self, other: ChunkCoord) -> i32 {
+        (self.x - other.x).abs().max((self.y - other.y).abs())
+    }
+}
+
+/// What is known about a chunk while its cells aren't loaded
+#[derive(Debug, Clone, Default)]
+pub struct ChunkSummary {
+    pub walkable_cells: u32,
+    pub average_height: f32,
+    /// Walkable cell closest to the chunk's center, used as a coarse path waypoint
+    pub waypoint: Option<GridCoord>,
+    /// Whether ground units can cross into the neighboring chunk towards +x, +y, -x and -y
+    pub open_edges: [bool; 4],
+}
+
+/// Authoritative cell data of the whole map, including chunks that aren't loaded as
+/// entities. Loaded cells are written back here whenever they change.
+#[derive(Resource, Debug, Default)]
+pub struct CellStore {
+    width: i32,
+    height: i32,
+    cells: Vec<GridCell>,
+}
+
+impl CellStore {
+    /// Store for a map's cells in row-major order (y * width + x)
+    pub fn new(width: i32, height: i32, cells: Vec<GridCell>) -> Self {
+        Self { width, height, cells }
+    }
+
+    pub fn get(&self, coord: GridCoord) -> Option<&GridCell> {
+        MapGrid::cell_index(self.width, self.height, coord).map(|index| &self.cells[index])
+    }
+
+    pub fn set(&mut self, coord: GridCoord, cell: GridCell) {
+        if let Some(index) = MapGrid::cell_index(self.width, self.height, coord) {
+            self.cells[index] = cell;
+        }
+    }
+
+    /// All cells in row-major order
+    pub fn cells(&self) -> &[GridCell] {
+        &self.cells
+    }
+
+    /// Summarize one chunk for coarse pathfinding
+    pub fn summarize(&self, chunk: ChunkCoord) -> ChunkSummary {
+        let center = chunk.origin().offset(CHUNK_SIZE / 2, CHUNK_SIZE / 2);
+        let mut summary = ChunkSummary::default();
+        let mut total_height = 0.0;
+        let mut count = 0;
+
+        for coord in chunk.cells() {
+            let Some(cell) = self.get(coord) else {
+                continue;
+            };
+            count += 1;
+            total_height += cell.height();
+            if !cell.walkable {
+                continue;
+            }
+            summary.walkable_cells += 1;
+            let closer = summary.waypoint.is_none_or(|waypoint| {
+                coord.chebyshev_distance(center) < waypoint.chebyshev_distance(center)
+            });
+            if closer {
+                summary.waypoint = Some(coord);
+            }
+        }
+        if count > 0 {
+            summary.average_height = total_height / count as f32;
+        }
+
+        for (edge, (dx, dy)) in CHUNK_DIRECTIONS.into_iter().enumerate() {
+            summary.open_edges[edge] = chunk.cells().any(|coord| {
+                let across = coord.offset(dx, dy);
+                if ChunkCoord::of(across) == chunk {
+                    return false;
+                }
+                match (self.get(coord), self.get(across)) {
+                    (Some(from), Some(to)) => from.walkable && to.walkable && levels_connected(from, to),
+                    _ => false,
+                }
+            });
+        }
+        summary
+    }
+}
+
+/// Chunk bookkeeping and coarse pathfinding over chunk summaries
+impl MapGrid {
+    /// Number of chunks along x and y
+    pub fn chunk_counts(&self) -> (i32, i32) {
+        ((self.width + CHUNK_SIZE - 1) / CHUNK_SIZE, (self.height + CHUNK_SIZE - 1) / CHUNK_SIZE)
+    }
+
+    /// Check if a chunk lies on the map
+    pub fn chunk_in_bounds(&self, chunk: ChunkCoord) -> bool {
+        let (columns, rows) = self.chunk_counts();
+        chunk.x >= 0 && chunk.x < columns && chunk.y >= 0 && chunk.y < rows
+    }
+
+    /// Check if the cell's chunk is loaded as entities
+    pub fn is_loaded(&self, coord: GridCoord) -> bool {
+        self.loaded_chunks.contains(&ChunkCoord::of(coord))
+    }
+
+    pub fn is_chunk_loaded(&self, chunk: ChunkCoord) -> bool {
+        self.loaded_chunks.contains(&chunk)
+    }
+
+    /// Check if every chunk of the map is loaded, so fine pathfinding sees all cells
+    pub fn all_chunks_loaded(&self) -> bool {
+        let (columns, rows) = self.chunk_counts();
+        self.loaded_chunks.len() == (columns * rows) as usize
+    }
+
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = &ChunkCoord> {
+        self.loaded_chunks.iter()
+    }
+
+    /// Record a chunk's cells as spawned and registered
+    pub fn mark_chunk_loaded(&mut self, chunk: ChunkCoord) {
+        self.loaded_chunks.insert(chunk);
+    }
+
+    /// Forget a chunk's cell entities after they were despawned
+    pub fn unload_chunk(&mut self, chunk: ChunkCoord) {
+        for coord in chunk.cells() {
+            self.cells.remove(&coord);
+        }
+        self.loaded_chunks.remove(&chunk);
+    }
+
+    pub fn chunk_summary(&self, chunk: ChunkCoord) -> Option<&ChunkSummary> {
+        self.chunk_summaries.get(&chunk)
+    }
+
+    pub fn set_chunk_summary(&mut self, chunk: ChunkCoord, summary: ChunkSummary) {
+        self.chunk_summaries.insert(chunk, summary);
+    }
+
+    /// Rough route between two cells over chunk summaries, for when the cells along the
+    /// way aren't all loaded: the start, each crossed chunk's waypoint, then the goal
+    pub fn find_coarse_path(&self, from: GridCoord, to: GridCoord) -> Option<Vec<GridCoord>> {
+        let start = ChunkCoord::of(from);
+        let goal = ChunkCoord::of(to);
+        if !self.chunk_in_bounds(start) || !self.chunk_in_bounds(goal) {
+            return None;
+        }
+
+        // Breadth-first over chunks connected by open edges
+        let mut came_from: HashMap<ChunkCoord, ChunkCoord> = HashMap::new();
+        let mut open = VecDeque::from([start]);
+        came_from.insert(start, start);
+        while let Some(chunk) = open.pop_front() {
+            if chunk == goal {
+                break;
+            }
+            let Some(summary) = self.chunk_summary(chunk) else {
+                continue;
+            };
+            for (edge, (dx, dy)) in CHUNK_DIRECTIONS.into_iter().enumerate() {
+                let next = ChunkCoord { x: chunk.x + dx, y: chunk.y + dy };
+                if summary.open_edges[edge] && self.chunk_in_bounds(next) && !came_from.contains_key(&next) {
+                    came_from.insert(next, chunk);
+                    open.push_back(next);
+                }
+            }
+        }
+        if !came_from.contains_key(&goal) {
+            return None;
+        }
+
+        let mut chunks = vec![goal];
+        let mut current = goal;
+        while current != start {
+            current = came_from[&current];
+            chunks.push(current);
+        }
+        chunks.reverse();
+
+        let mut path = vec![from];
+        let crossed = chunks.iter().skip(1).take(chunks.len().saturating_sub(2));
+        path.extend(crossed.filter_map(|chunk| self.chunk_summary(*chunk).and_then(|summary| summary.waypoint)));
+        if to != from {
+            path.push(to);
+        }
+        Some(path)
+    }
+}
@@ -1,5 +1,6 @@
 use bevy::prelude::*;
-use super::grid::{GridCoord, TerrainType};
+use super::grid::{GridCoord, GridCell};
+use super::terrain::{MovementClass, TerrainType};
 use crate::components::faction::FactionId;
 
 /// Event for when a map is loaded
@@ -15,9 +16,45 @@ pub struct MapLoadedEvent {
 pub struct TerrainModifiedEvent {
     pub coord: GridCoord,
     pub new_terrain: TerrainType,
+    /// Overrides applied after the terrain type's default properties
+    pub elevation: Option<f32>,
+    pub cliff_level: Option<i32>,
+    pub ramp: Option<bool>,
+    pub walkable: Option<bool>,
+    pub buildable: Option<bool>,
     pub timestamp: f64,
 }
 
+impl TerrainModifiedEvent {
+    /// Change the terrain type, using its default properties
+    pub fn terrain(coord: GridCoord, new_terrain: TerrainType, timestamp: f64) -> Self {
+        Self {
+            coord,
+            new_terrain,
+            elevation: None,
+            cliff_level: None,
+            ramp: None,
+            walkable: None,
+            buildable: None,
+            timestamp,
+        }
+    }
+
+    /// Set a cell to exactly the given state
+    pub fn set_cell(coord: GridCoord, cell: &GridCell, timestamp: f64) -> Self {
+        Self {
+            coord,
+            new_terrain: cell.terrain.clone(),
+            elevation: Some(cell.elevation),
+            cliff_level: Some(cell.cliff_level),
+            ramp: Some(cell.ramp),
+            walkable: Some(cell.walkable),
+            buildable: Some(cell.buildable),
+            timestamp,
+        }
+    }
+}
+
 /// Event for when a unit moves to a grid position
 #[derive(Event)]
 pub struct UnitMoveEvent {
@@ -27,6 +64,24 @@ pub struct UnitMoveEvent {
     pub timestamp: f64,
 }
 
+/// Event for when a unit enters a named region
+#[derive(Event)]
+pub struct UnitEnteredRegion {
+    pub entity: Entity,
+    pub region: String,
+    pub coord: GridCoord,
+    pub timestamp: f64,
+}
+
+/// Event for when a unit leaves a named region
+#[derive(Event)]
+pub struct UnitLeftRegion {
+    pub entity: Entity,
+    pub region: String,
+    pub coord: GridCoord,
+    pub timestamp: f64,
+}
+
 /// Event for when a building is placed
 #[derive(Event)]
 pub struct BuildingPlacedEvent {
@@ -43,6 +98,8 @@ pub struct TerrainRevealedEvent {
     pub center: GridCoord,
     pub radius: i32,
     pub faction: FactionId,
+    /// Clip the revealed area by line of sight from the center
+    pub line_of_sight: bool,
     pub timestamp: f64,
 }
 
@@ -50,8 +107,11 @@ pub struct TerrainRevealedEvent {
 #[derive(Event)]
 pub struct PathfindingRequestEvent {
     pub entity: Entity,
+    /// Number the requester gave the request, handed back with its result
+    pub request: u32,
     pub from: GridCoord, 
     pub to: GridCoord,
+    pub movement: MovementClass,
     pub timestamp: f64,
 }
 
@@ -59,6 +119,7 @@ pub struct PathfindingRequestEvent {
 #[derive(Event)]
 pub struct PathfindingResultEvent {
     pub entity: Entity,
+    pub request: u32,
     pub path: Vec<GridCoord>,
     pub success: bool,
     pub timestamp: f64,
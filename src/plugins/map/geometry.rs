@@ -0,0 +1,242 @@
+use super::grid::{GridCoord, MapGrid};
+
+/// Offsets of the four orthogonal neighbors
+const ORTHOGONAL: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+/// Offsets of the four diagonal neighbors
+const DIAGONAL: [(i32, i32); 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+
+/// Square-grid geometry on plain coordinates, without regard to map bounds or topology
+impl GridCoord {
+    pub fn offset(self, dx: i32, dy: i32) -> GridCoord {
+        GridCoord { x: self.x + dx, y: self.y + dy }
+    }
+
+    /// The four cells sharing an edge with this one
+    pub fn neighbors4(self) -> impl Iterator<Item = GridCoord> {
+        ORTHOGONAL.into_iter().map(move |(dx, dy)| self.offset(dx, dy))
+    }
+
+    /// The eight cells sharing an edge or corner with this one, orthogonal first
+    pub fn neighbors8(self) -> impl Iterator<Item = GridCoord> {
+        ORTHOGONAL.into_iter().chain(DIAGONAL).map(move |(dx, dy)| self.offset(dx, dy))
+    }
+
+    /// Steps between two cells when moving in eight directions
+    pub fn chebyshev_distance(self, other: GridCoord) -> i32 {
+        (self.x - other.x).abs().max((self.y - other.y).abs())
+    }
+
+    /// Steps between two cells when moving in four directions
+    pub fn manhattan_distance(self, other: GridCoord) -> i32 {
+        (self.x - other.x).abs() + (self.y - other.y).abs()
+    }
+
+    /// Length of the shortest eight-direction walk, with diagonal steps costing √2
+    pub fn octile_distance(self, other: GridCoord) -> f32 {
+        let dx = (self.x - other.x).abs() as f32;
+        let dy = (self.y - other.y).abs() as f32;
+        dx.max(dy) + (std::f32::consts::SQRT_2 - 1.0) * dx.min(dy)
+    }
+
+    /// Cells crossed by a straight line to `other` (Bresenham), both ends included
+    pub fn line_to(self, other: GridCoord) -> Vec<GridCoord> {
+        let dx = (other.x - self.x).abs();
+        let dy = -(other.y - self.y).abs();
+        let step_x = if self.x < other.x { 1 } else { -1 };
+        let step_y = if self.y < other.y { 1 } else { -1 };
+        let mut error = dx + dy;
+        let mut current = self;
+        let mut cells = vec![current];
+
+        while current != other {
+            let doubled = error * 2;
+            if doubled >= dy {
+                error += dy;
+                current.x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                current.y += step_y;
+            }
+            cells.push(current);
+        }
+        cells
+    }
+
+    /// Every cell in the rectangle between this cell and `other`, both corners included
+    pub fn rect_to(self, other: GridCoord) -> impl Iterator<Item = GridCoord> {
+        let (min_x, max_x) = (self.x.min(other.x), self.x.max(other.x));
+        let (min_y, max_y) = (self.y.min(other.y), self.y.max(other.y));
+        (min_y..=max_y).flat_map(move |y| (min_x..=max_x).map(move |x| GridCoord { x, y }))
+    }
+
+    /// Cells at exactly Chebyshev distance `radius`: the outline of a square
+    pub fn square_ring(self, radius: i32) -> Vec<GridCoord> {
+        if radius <= 0 {
+            return vec![self];
+        }
+
+        let mut cells = Vec::with_capacity((radius * 8) as usize);
+        for d in -radius..=radius {
+            cells.push(self.offset(d, -radius));
+            cells.push(self.offset(d, radius));
+        }
+        for d in (-radius + 1)..radius {
+            cells.push(self.offset(-radius, d));
+            cells.push(self.offset(radius, d));
+        }
+        cells
+    }
+
+    /// Cells covered by a building `width` by `height` cells with this cell as its
+    /// minimum corner
+    pub fn footprint(self, width: i32, height: i32) -> impl Iterator<Item = GridCoord> {
+        (0..height).flat_map(move |dy| (0..width).map(move |dx| self.offset(dx, dy)))
+    }
+}
+
+/// Map-aware area iteration, clipped to the map and following its topology
+impl MapGrid {
+    /// Every cell in the rectangle between two corner cells (inclusive) that lies on the map
+    pub fn rect(&self, a: GridCoord, b: GridCoord) -> impl Iterator<Item = GridCoord> {
+        let min = GridCoord { x: a.x.min(b.x).max(0), y: a.y.min(b.y).max(0) };
+        let max = GridCoord {
+            x: a.x.max(b.x).min(self.width - 1),
+            y: a.y.max(b.y).min(self.height - 1),
+        };
+        // Nothing to visit when the rectangle misses the map entirely
+        let overlaps = min.x <= max.x && min.y <= max.y;
+        overlaps.then(|| min.rect_to(max)).into_iter().flatten()
+    }
+
+    /// Cells on the map within `radius` cells of `center` (a filled circle)
+    pub fn circle(&self, center: GridCoord, radius: f32) -> impl Iterator<Item = GridCoord> + '_ {
+        self.cells_around(center, radius)
+            .filter(move |coord| self.distance(center, *coord) <= radius)
+    }
+
+    /// Cells on the map at `radius` cells from `center`: those in the circle of that
+    /// radius but not in the one a cell smaller, so rings of growing radius tile the circle
+    pub fn ring(&self, center: GridCoord, radius: f32) -> impl Iterator<Item = GridCoord> + '_ {
+        self.cells_around(center, radius).filter(move |coord| {
+            let distance = self.distance(center, *coord);
+            distance <= radius && distance > radius - 1.0
+        })
+    }
+
+    /// Cells of a building footprint, or `None` if any of it lies off the map
+    pub fn footprint(&self, origin: GridCoord, width: i32, height: i32) -> Option<Vec<GridCoord>> {
+        let cells: Vec<GridCoord> = origin.footprint(width, height).collect();
+        cells.iter().all(|coord| self.in_bounds(*coord)).then_some(cells)
+    }
+
+    /// Cells on the map inside the offset box around `center`, which contains the circle
+    /// of `radius` on every topology
+    fn cells_around(&self, center: GridCoord, radius: f32) -> impl Iterator<Item = GridCoord> {
+        let reach = radius.max(0.0).floor() as i32;
+        self.rect(center.offset(-reach, -reach), center.offset(reach, reach))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::map::topology::GridTopology;
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+
+    fn coord() -> impl Strategy<Value = GridCoord> {
+        (-20..20, -20..20).prop_map(|(x, y)| GridCoord { x, y })
+    }
+
+    /// Width, height and whether the cells are hexagons
+    fn shape() -> impl Strategy<Value = (i32, i32, bool)> {
+        (1..16, 1..16, any::<bool>())
+    }
+
+    fn grid((width, height, hex): (i32, i32, bool)) -> MapGrid {
+        let mut grid = MapGrid::new(width, height, 1.0);
+        if hex {
+            grid.topology = GridTopology::Hex;
+        }
+        grid
+    }
+
+    proptest! {
+        #[test]
+        fn lines_are_contiguous_and_include_both_ends(from in coord(), to in coord()) {
+            let line = from.line_to(to);
+            prop_assert_eq!(line.first(), Some(&from));
+            prop_assert_eq!(line.last(), Some(&to));
+            prop_assert_eq!(line.len() as i32, from.chebyshev_distance(to) + 1);
+            for step in line.windows(2) {
+                prop_assert_eq!(step[0].chebyshev_distance(step[1]), 1);
+            }
+        }
+
+        #[test]
+        fn square_rings_are_outlines_at_their_radius(center in coord(), radius in 1..12) {
+            let ring = center.square_ring(radius);
+            let distinct: HashSet<GridCoord> = ring.iter().copied().collect();
+            prop_assert_eq!(ring.len() as i32, 8 * radius);
+            prop_assert_eq!(distinct.len(), ring.len());
+            prop_assert!(ring.iter().all(|coord| coord.chebyshev_distance(center) == radius));
+        }
+
+        #[test]
+        fn rings_tile_the_circle(shape in shape(), center in coord(), radius in 0..10) {
+            let grid = grid(shape);
+            let mut tiled = HashSet::new();
+            for ring_radius in 0..=radius {
+                for coord in grid.ring(center, ring_radius as f32) {
+                    prop_assert!(tiled.insert(coord), "{:?} lies on two rings", coord);
+                }
+            }
+            let circle: HashSet<GridCoord> = grid.circle(center, radius as f32).collect();
+            prop_assert_eq!(tiled, circle);
+        }
+
+        #[test]
+        fn rects_stay_on_the_map(shape in shape(), a in coord(), b in coord()) {
+            let grid = grid(shape);
+            let cells: Vec<GridCoord> = grid.rect(a, b).collect();
+            prop_assert!(cells.iter().all(|coord| grid.in_bounds(*coord)));
+            let expected = a.rect_to(b).filter(|coord| grid.in_bounds(*coord)).count();
+            prop_assert_eq!(cells.len(), expected);
+        }
+
+        #[test]
+        fn footprints_fit_the_map_or_are_refused(shape in shape(), origin in coord(), width in 1..5, height in 1..5) {
+            let grid = grid(shape);
+            let corner = origin.offset(width - 1, height - 1);
+            let fits = grid.in_bounds(origin) && grid.in_bounds(corner);
+            match grid.footprint(origin, width, height) {
+                Some(cells) => {
+                    prop_assert!(fits);
+                    prop_assert_eq!(cells.len() as i32, width * height);
+                    prop_assert_eq!(cells.into_iter().collect::<HashSet<_>>(), origin.rect_to(corner).collect());
+                }
+                None => prop_assert!(!fits),
+            }
+        }
+
+        #[test]
+        fn distances_are_ordered(a in coord(), b in coord()) {
+            let chebyshev = a.chebyshev_distance(b);
+            let manhattan = a.manhattan_distance(b);
+            let octile = a.octile_distance(b);
+            prop_assert!(octile >= chebyshev as f32);
+            prop_assert!(octile <= manhattan as f32 + 1e-3);
+            prop_assert!(manhattan >= chebyshev);
+        }
+
+        #[test]
+        fn orthogonal_neighbors_share_an_edge(center in coord()) {
+            let neighbors: HashSet<GridCoord> = center.neighbors4().collect();
+            let all: HashSet<GridCoord> = center.neighbors8().collect();
+            prop_assert_eq!(neighbors.len(), 4);
+            prop_assert!(neighbors.iter().all(|coord| center.manhattan_distance(*coord) == 1));
+            prop_assert!(neighbors.is_subset(&all));
+        }
+    }
+}
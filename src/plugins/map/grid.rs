@@ -1,39 +1,54 @@
 use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
+use serde::{Deserialize, Serialize};
+use super::terrain::{TerrainRegistry, TerrainType};
+use super::topology::GridTopology;
+use super::chunks::{ChunkCoord, ChunkSummary};
 
 /// Grid coordinates for map locations (separate from world Transform)
-#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct GridCoord {
     pub x: i32,
     pub y: i32,
 }
 
-/// Defines terrain types for each grid cell
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum TerrainType {
-    Grass,
-    Dirt,
-    Stone,
-    Water,
-    Forest,
-    Mountain,
-}
+/// World height of one cliff level
+pub const CLIFF_HEIGHT: f32 = 1.0;
+/// Highest cliff level a cell can have
+pub const MAX_CLIFF_LEVEL: i32 = 4;
 
 /// Properties of individual grid cells
-#[derive(Component, Debug, Clone)]
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GridCell {
     pub terrain: TerrainType,
     pub walkable: bool,
     pub buildable: bool,
+    /// Height offset on top of the cliff level, for visual variation only
     pub elevation: f32,
+    /// Discrete cliff level; units only move between levels over ramps
+    #[serde(default)]
+    pub cliff_level: i32,
+    /// Ramps connect the cell to walkable neighbors one cliff level higher
+    #[serde(default)]
+    pub ramp: bool,
+}
+
+impl GridCell {
+    /// World height of the cell's ground
+    pub fn height(&self) -> f32 {
+        self.cliff_level as f32 * CLIFF_HEIGHT + self.elevation
+    }
 }
 
 impl Default for GridCell {
     fn default() -> Self {
         Self {
-            terrain: TerrainType::Grass,
+            terrain: TerrainType::default(),
             walkable: true,
             buildable: true,
             elevation: 0.0,
+            cliff_level: 0,
+            ramp: false,
         }
     }
 }
@@ -44,8 +59,17 @@ pub struct MapGrid {
     pub width: i32,
     pub height: i32,
     pub cell_size: f32,
-    /// Maps grid coordinates to entity IDs containing the cell data
-    cells: HashMap<GridCoord, Entity>,
+    /// How cells tile the map
+    pub topology: GridTopology,
+    /// Maps grid coordinates to entity IDs containing the cell data, for loaded chunks
+    pub(super) cells: HashMap<GridCoord, Entity>,
+    /// Chunks whose cells are currently spawned as entities
+    pub(super) loaded_chunks: HashSet<ChunkCoord>,
+    /// Summaries of every chunk, loaded or not
+    pub(super) chunk_summaries: HashMap<ChunkCoord, ChunkSummary>,
+    /// Ground height at the four corners of each cell, row-major, in the same corner
+    /// order as the terrain mesh: (0,0), (1,0), (1,1), (0,1)
+    surface: Vec<[f32; 4]>,
 }
 
 impl MapGrid {
@@ -55,7 +79,11 @@ impl MapGrid {
             width,
             height,
             cell_size,
+            topology: GridTopology::Square,
             cells: HashMap::new(),
+            loaded_chunks: HashSet::new(),
+            chunk_summaries: HashMap::new(),
+            surface: Vec::new(),
         }
     }
 
@@ -72,25 +100,114 @@ impl MapGrid {
     /// Convert world position to grid coordinates
     pub fn world_to_grid(&self, world_pos: Vec3) -> GridCoord {
         // In RTS games, typically using X and Z as the ground plane
-        GridCoord {
-            x: (world_pos.x / self.cell_size).floor() as i32,
-            y: (world_pos.z / self.cell_size).floor() as i32,
-        }
+        self.topology.world_to_cell(Vec2::new(world_pos.x, world_pos.z), self.cell_size)
     }
     
-    /// Convert grid coordinates to world position (centered in cell)
-    pub fn grid_to_world(&self, coord: GridCoord, elevation: f32) -> Vec3 {
+    /// Convert grid coordinates to a world position on the ground at the cell's center
+    pub fn grid_to_world(&self, coord: GridCoord) -> Vec3 {
+        self.grid_to_world_at(coord, self.cell_height(coord))
+    }
+
+    /// Convert grid coordinates to world position (centered in cell) at an explicit height
+    pub fn grid_to_world_at(&self, coord: GridCoord, elevation: f32) -> Vec3 {
+        let center = self.topology.cell_center(coord, self.cell_size);
         Vec3::new(
-            (coord.x as f32 + 0.5) * self.cell_size,
+            center.x,
             elevation, // Y is up in Bevy's coordinate system
-            (coord.y as f32 + 0.5) * self.cell_size,
+            center.y,
         )
     }
+
+    /// Ground-plane (XZ) corners of a cell, in order around it
+    pub fn cell_corners(&self, coord: GridCoord) -> Vec<Vec2> {
+        self.topology.cell_corners(coord, self.cell_size)
+    }
+
+    /// Ground-plane (XZ) size of the area from the world origin the map covers
+    pub fn world_extent(&self) -> Vec2 {
+        self.topology.map_extent(self.width, self.height, self.cell_size)
+    }
+
+    /// Neighboring cells of a cell, whether or not they are on the map
+    pub fn neighbors(&self, coord: GridCoord) -> Vec<GridCoord> {
+        self.topology.neighbors(coord)
+    }
+
+    /// Distance between two cells, in cells
+    pub fn distance(&self, a: GridCoord, b: GridCoord) -> f32 {
+        self.topology.distance(a, b)
+    }
+
+    /// Cells crossed by a straight line between two cells, both ends included
+    pub fn line(&self, from: GridCoord, to: GridCoord) -> Vec<GridCoord> {
+        self.topology.line(from, to)
+    }
+
+    /// Replace the ground surface heights, kept in step with the cells by the terrain mesh
+    pub fn set_surface(&mut self, surface: Vec<[f32; 4]>) {
+        self.surface = surface;
+    }
+
+    /// Ground height at each corner of a cell; zero outside the map or before the
+    /// surface has been built
+    pub fn corner_heights(&self, coord: GridCoord) -> [f32; 4] {
+        if !self.in_bounds(coord) {
+            return [0.0; 4];
+        }
+        Self::cell_index(self.width, self.height, coord)
+            .and_then(|index| self.surface.get(index))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Ground height at the center of a cell
+    pub fn cell_height(&self, coord: GridCoord) -> f32 {
+        self.corner_heights(coord).iter().sum::<f32>() / 4.0
+    }
+
+    /// Ground height at a world XZ position, interpolated across its cell's surface
+    pub fn sample_height(&self, x: f32, z: f32) -> f32 {
+        let coord = self.world_to_grid(Vec3::new(x, 0.0, z));
+        // Hex cells are flat
+        if self.topology == GridTopology::Hex {
+            return self.cell_height(coord);
+        }
+        let [c00, c10, c11, c01] = self.corner_heights(coord);
+        let fx = (x / self.cell_size - coord.x as f32).clamp(0.0, 1.0);
+        let fz = (z / self.cell_size - coord.y as f32).clamp(0.0, 1.0);
+        let near = c00 + (c10 - c00) * fx;
+        let far = c01 + (c11 - c01) * fx;
+        near + (far - near) * fz
+    }
     
     /// Check if coordinates are within map bounds
     pub fn in_bounds(&self, coord: GridCoord) -> bool {
         coord.x >= 0 && coord.x < self.width && coord.y >= 0 && coord.y < self.height
     }
+
+    /// Index of a cell in row-major (y * width + x) per-cell data of a `width` by
+    /// `height` map, or `None` off the map
+    pub fn cell_index(width: i32, height: i32, coord: GridCoord) -> Option<usize> {
+        let in_bounds = coord.x >= 0 && coord.x < width && coord.y >= 0 && coord.y < height;
+        in_bounds.then(|| (coord.y * width + coord.x) as usize)
+    }
+}
+
+/// System parameter for looking up cell data by grid coordinate
+#[derive(SystemParam)]
+pub struct GridCells<'w, 's> {
+    pub grid: Res<'w, MapGrid>,
+    pub terrains: Res<'w, TerrainRegistry>,
+    cells: Query<'w, 's, &'static GridCell>,
+}
+
+impl GridCells<'_, '_> {
+    /// Get the cell at the specified grid coordinates
+    pub fn get(&self, coord: GridCoord) -> Option<&GridCell> {
+        self.grid
+            .get_cell_entity(coord)
+            .and_then(|entity| self.cells.get(*entity).ok())
+    }
 }
 
-use std::collections::HashMap;
\ No newline at end of file
+use std::collections::{HashMap, HashSet};
\ No newline at end of file
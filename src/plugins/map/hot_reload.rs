@@ -0,0 +1,98 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use super::{
+    chunks::CellStore,
+    events::{MapLoadedEvent, TerrainModifiedEvent},
+    grid::{GridCoord, MapGrid},
+    loader::LoadMapCommand,
+    map_file::MapFile,
+    streaming::refresh_summaries,
+    terrain::TerrainRegistry,
+    LoadedMap,
+};
+
+/// System parameter with the live terrain a reloaded map file is compared against
+#[derive(SystemParam)]
+pub struct LiveTerrain<'w> {
+    grid: ResMut<'w, MapGrid>,
+    store: Option<ResMut<'w, CellStore>>,
+    terrains: ResMut<'w, TerrainRegistry>,
+}
+
+/// Watch the file of each newly loaded map through the asset server, which reports
+/// edits made to it on disk
+pub fn watch_map_file(
+    asset_server: Res<AssetServer>,
+    mut loaded_events: EventReader<MapLoadedEvent>,
+    mut loaded_map: ResMut<LoadedMap>,
+) {
+    let Some(event) = loaded_events.read().last() else {
+        return;
+    };
+    // Generated maps have no file to watch
+    loaded_map.file = MapFile::path(&event.map_name)
+        .exists()
+        .then(|| asset_server.load(MapFile::asset_path(&event.map_name)));
+}
+
+/// Reload the terrain of the current map when its file changes. Cells that differ from
+/// the live grid are set through `TerrainModifiedEvent`s, leaving units where they are;
+/// a change of size or topology reloads the whole map instead.
+pub fn hot_reload_map(
+    mut asset_events: EventReader<AssetEvent<MapFile>>,
+    map_files: Res<Assets<MapFile>>,
+    loaded_map: Res<LoadedMap>,
+    time: Res<Time>,
+    live: LiveTerrain,
+    mut terrain_events: EventWriter<TerrainModifiedEvent>,
+    mut load_commands: EventWriter<LoadMapCommand>,
+) {
+    let Some(file) = loaded_map.file.as_ref() else {
+        asset_events.clear();
+        return;
+    };
+    // A file that fails to load keeps the previous version, and with it the current terrain
+    let modified = asset_events.read().fold(false, |modified, event| modified | event.is_modified(file));
+    if !loaded_map.loaded || !modified {
+        return;
+    }
+    let Some(map) = map_files.get(file) else {
+        return;
+    };
+    let LiveTerrain { mut grid, store, mut terrains } = live;
+    let Some(mut store) = store else {
+        return;
+    };
+
+    let same_shape = map.width == grid.width
+        && map.height == grid.height
+        && map.cell_size == grid.cell_size
+        && map.topology == grid.topology;
+    if !same_shape {
+        info!("Map {} changed shape, reloading it", map.name);
+        load_commands.write(LoadMapCommand { map_name: loaded_map.name.clone() });
+        return;
+    }
+
+    if terrains.map_terrains() != map.terrains.as_slice() {
+        terrains.set_map_terrains(&map.terrains);
+    }
+
+    let timestamp = time.elapsed_secs_f64();
+    let mut unloaded = Vec::new();
+    for (index, cell) in map.cells.iter().enumerate() {
+        let coord = GridCoord { x: index as i32 % map.width, y: index as i32 / map.width };
+        if store.get(coord) == Some(cell) {
+            continue;
+        }
+        if grid.is_loaded(coord) {
+            terrain_events.write(TerrainModifiedEvent::set_cell(coord, cell, timestamp));
+        } else {
+            // No entity to modify; the chunk picks the cell up when it streams in
+            store.set(coord, cell.clone());
+            unloaded.push(coord);
+        }
+    }
+    refresh_summaries(grid.bypass_change_detection(), &store, unloaded);
+    info!("Reloaded terrain of map {}", map.name);
+}
@@ -0,0 +1,50 @@
+use bevy::prelude::*;
+use super::grid::{GridCoord, GridCell, GridCells};
+
+/// Cliff level a viewer standing on the cell sees from; ramps look up onto the level above
+fn eye_level(cell: &GridCell) -> i32 {
+    cell.cliff_level + cell.ramp as i32
+}
+
+impl GridCells<'_, '_> {
+    /// Whether a cell's terrain stops vision passing through it
+    fn blocks_vision(&self, cell: &GridCell) -> bool {
+        self.terrains.get(&cell.terrain).blocks_vision
+    }
+
+    /// Check if a viewer standing in `from` can see into `to`.
+    ///
+    /// The viewer's eye is at the cliff level of its own cell. Any cell on the way on a
+    /// higher level, or a vision-blocking terrain at eye level, stops the line. High
+    /// ground is hidden from below, but blockers themselves (e.g. the edge of a forest or
+    /// a mountain face) can still be seen.
+    pub fn has_line_of_sight(&self, from: GridCoord, to: GridCoord) -> bool {
+        // Cells missing from the grid count as flat, open ground
+        let eye = self.get(from).map_or(0, eye_level);
+
+        let line = self.grid.line(from, to);
+        for coord in line.iter().skip(1).take(line.len().saturating_sub(2)) {
+            let Some(cell) = self.get(*coord) else {
+                continue;
+            };
+            if cell.cliff_level > eye || (self.blocks_vision(cell) && cell.cliff_level >= eye) {
+                return false;
+            }
+        }
+
+        match self.get(to) {
+            Some(target) => target.cliff_level <= eye || self.blocks_vision(target),
+            None => true,
+        }
+    }
+
+    /// Check if a world position is within `range` of another and visible from it,
+    /// for targeting and ability range checks
+    pub fn in_sight_range(&self, from: Vec3, to: Vec3, range: f32) -> bool {
+        let planar = Vec2::new(to.x - from.x, to.z - from.z);
+        if planar.length_squared() > range * range {
+            return false;
+        }
+        self.has_line_of_sight(self.grid.world_to_grid(from), self.grid.world_to_grid(to))
+    }
+}
@@ -1,10 +1,66 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
+use bevy::render::mesh::PrimitiveTopology;
+use bevy::render::render_asset::RenderAssetUsages;
 use super::{
-    grid::{GridCoord, GridCell, TerrainType, MapGrid},
+    grid::{GridCoord, GridCell, MapGrid},
+    streaming::install_cells,
+    terrain::{TerrainRegistry, TerrainType},
     events::MapLoadedEvent,
+    map_file::{MapFile, MapObjects},
+    regions::RegionRegistry,
     LoadedMap,
 };
 use crate::plugins::camera::CameraGround;
+use crate::plugins::triggers::TriggerSet;
+
+/// Entities of a loaded map that are replaced when another map loads
+type MapEntityFilter = Or<(With<GridCell>, With<super::TerrainMesh>)>;
+
+/// System parameter with the assets and bookkeeping needed to spawn a map
+#[derive(SystemParam)]
+pub struct MapSpawner<'w> {
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<StandardMaterial>>,
+    map_loaded_events: EventWriter<'w, MapLoadedEvent>,
+    loaded_map: ResMut<'w, LoadedMap>,
+}
+
+impl MapSpawner<'_> {
+    /// Spawn the ground mesh entity; its geometry is built from the cells once they exist
+    pub fn spawn_terrain_mesh(&mut self, commands: &mut Commands) {
+        let mesh = self.meshes.add(Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default()));
+        
+        // Terrain colors come from the mesh's vertex colors
+        let material = self.materials.add(StandardMaterial {
+            base_color: Color::WHITE,
+            perceptual_roughness: 0.9,
+            ..default()
+        });
+        
+        // Create main terrain entity with camera ground component
+        commands.spawn((
+            // Core components (Mesh3d requires Material3d)
+            Mesh3d(mesh),
+            MeshMaterial3d(material),
+            Transform::default(),
+            super::TerrainMesh,
+            CameraGround,
+        ));
+    }
+
+    /// Announce a newly spawned map and record it as the loaded one
+    pub fn finish(&mut self, map_name: &str, width: i32, height: i32) {
+        self.map_loaded_events.write(MapLoadedEvent {
+            map_name: map_name.to_string(),
+            width,
+            height,
+        });
+        
+        self.loaded_map.name = map_name.to_string();
+        self.loaded_map.loaded = true;
+    }
+}
 
 /// System for loading a map from a file or specific configuration
 pub fn load_map(
@@ -13,109 +69,80 @@ pub fn load_map(
     height: i32,
     cell_size: f32,
     commands: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
-    map_loaded_events: &mut EventWriter<MapLoadedEvent>,
-    loaded_map: &mut ResMut<LoadedMap>,
+    spawner: &mut MapSpawner,
+    terrains: &TerrainRegistry,
 ) {
-    // Clear existing map entities
-    // In a real implementation, you'd clean up the existing map entities first
-    
-    // Create ground plane mesh
-    let mesh = meshes.add(
-        Plane3d::default()
-            .mesh()
-            .size(cell_size * width as f32, cell_size * height as f32)
-            .subdivisions(width as u32)
-    );
-    
-    // Basic green material for ground
-    let material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.3, 0.5, 0.3),
-        perceptual_roughness: 0.9,
-        ..default()
-    });
+    spawner.spawn_terrain_mesh(commands);
     
-    // Create main terrain entity with camera ground component
-    commands.spawn((
-        // Core components (Mesh3d requires Material3d)
-        Mesh3d(mesh),
-        MeshMaterial3d(material),
-        Transform::from_xyz(
-            (width as f32 * cell_size) / 2.0 - (cell_size / 2.0),
-            0.0,
-            (height as f32 * cell_size) / 2.0 - (cell_size / 2.0),
-        ),
-        super::TerrainMesh,
-        CameraGround,
-    ));
+    let mut grid = MapGrid::new(width, height, cell_size);
     
-    // Create grid resource
-    commands.insert_resource(MapGrid::new(width, height, cell_size));
+    // Procedural map used when no map file is available
     
-    // In a real implementation, you'd load the map data from a file here
-    // For now, we'll create a simple procedural map
-    
-    // Generate grid cells
-    for x in 0..width {
-        for y in 0..height {
-            let coord = GridCoord { x, y };
-            
+    // Generate grid cells in row-major order; their entities are spawned as chunks stream in
+    let mut cells = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
             // Create some procedural terrain features
             let terrain = match (x, y) {
                 // Water around the edges
-                (0..=3, _) | (_, 0..=3) => TerrainType::Water,
-                (x_val, _) if x_val >= width - 4 => TerrainType::Water,
-                (_, y_val) if y_val >= height - 4 => TerrainType::Water,
+                (0..=3, _) | (_, 0..=3) => "water",
+                (x_val, _) if x_val >= width - 4 => "water",
+                (_, y_val) if y_val >= height - 4 => "water",
                 
                 // Some mountains
                 (x, y) if (x as f32 - width as f32 / 3.0).powi(2) + 
-                          (y as f32 - height as f32 / 3.0).powi(2) < 10.0 => TerrainType::Mountain,
+                          (y as f32 - height as f32 / 3.0).powi(2) < 10.0 => "mountain",
                 
                 // Some forests
-                (x, y) if (x + y) % 7 == 0 => TerrainType::Forest,
+                (x, y) if (x + y) % 7 == 0 => "forest",
                 
                 // Default to grass
-                _ => TerrainType::Grass,
+                _ => "grass",
             };
             
-            let mut cell = GridCell::default();
-            cell.terrain = terrain;
-            
             // Set properties based on terrain type
-            match terrain {
-                TerrainType::Water => {
-                    cell.walkable = false;
-                    cell.buildable = false;
-                },
-                TerrainType::Mountain => {
-                    cell.walkable = false;
-                    cell.buildable = false;
-                    cell.elevation = 2.0;
-                },
-                TerrainType::Forest => {
-                    cell.buildable = false;
-                },
-                _ => {
-                    // Default already set
-                }
-            }
-            
-            // Spawn the cell entity
-            commands.spawn((coord, cell));
+            let mut cell = GridCell {
+                terrain: TerrainType::new(terrain),
+                ..default()
+            };
+            terrains.get(&cell.terrain).apply_defaults(&mut cell);
+            cells.push(cell);
         }
     }
     
-    // Send map loaded event
-    map_loaded_events.write(MapLoadedEvent {
-        map_name: map_name.to_string(),
-        width,
-        height,
+    install_cells(commands, &mut grid, cells);
+    commands.insert_resource(grid);
+    commands.insert_resource(MapObjects::default());
+    commands.insert_resource(RegionRegistry::default());
+    commands.insert_resource(TriggerSet::default());
+    
+    spawner.finish(map_name, width, height);
+}
+
+/// Spawn a map from a parsed map file
+pub fn spawn_map_file(map: &MapFile, commands: &mut Commands, spawner: &mut MapSpawner) {
+    spawner.spawn_terrain_mesh(commands);
+    
+    let mut grid = MapGrid::new(map.width, map.height, map.cell_size);
+    grid.topology = map.topology;
+    let cells = (0..map.height)
+        .flat_map(|y| (0..map.width).map(move |x| GridCoord { x, y }))
+        .map(|coord| map.cell(coord).cloned().unwrap_or_default())
+        .collect();
+    install_cells(commands, &mut grid, cells);
+    commands.insert_resource(grid);
+    
+    commands.insert_resource(MapObjects {
+        start_locations: map.start_locations.clone(),
+        resources: map.resources.clone(),
     });
     
-    // Update loaded map resource
-    loaded_map.name = map_name.to_string();
-    loaded_map.loaded = true;
+    let mut regions = RegionRegistry::default();
+    regions.set_regions(map.regions.clone());
+    commands.insert_resource(regions);
+    commands.insert_resource(TriggerSet::new(map.triggers.clone()));
+    
+    spawner.finish(&map.name, map.width, map.height);
 }
 
 /// Command to load a specific map
@@ -127,25 +154,38 @@ pub struct LoadMapCommand {
 /// System to handle load map commands
 pub fn handle_load_map_commands(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut map_loaded_events: EventWriter<MapLoadedEvent>,
-    mut loaded_map: ResMut<LoadedMap>,
+    mut spawner: MapSpawner,
     mut load_events: EventReader<LoadMapCommand>,
+    mut terrains: ResMut<TerrainRegistry>,
+    existing: Query<Entity, MapEntityFilter>,
 ) {
     for event in load_events.read() {
-        // In a real implementation, you'd load different maps based on the name
-        // For now, we just have one map configuration
+        // Clear the previous map's cells and terrain mesh
+        for entity in existing.iter() {
+            commands.entity(entity).despawn();
+        }
+        
+        // Prefer a saved map file, falling back to the procedural map
+        match MapFile::load(&event.map_name) {
+            Ok(map) => {
+                terrains.set_map_terrains(&map.terrains);
+                spawn_map_file(&map, &mut commands, &mut spawner);
+                continue;
+            }
+            Err(error) => {
+                warn!("Falling back to procedural map: {error}");
+            }
+        }
+        
+        terrains.set_map_terrains(&[]);
         load_map(
             &event.map_name,
             64, // Larger map
             64,
             1.0,
             &mut commands,
-            &mut meshes,
-            &mut materials,
-            &mut map_loaded_events,
-            &mut loaded_map,
+            &mut spawner,
+            &terrains,
         );
     }
 }
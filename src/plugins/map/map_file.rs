@@ -0,0 +1,183 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use super::grid::{GridCoord, GridCell, MapGrid};
+use super::regions::Region;
+use super::terrain::{read_legacy_terrains, TerrainDef};
+use super::topology::GridTopology;
+use crate::plugins::triggers::Trigger;
+
+/// Directory map files are read from and saved to
+pub const MAP_DIRECTORY: &str = "assets/maps";
+/// `MAP_DIRECTORY` relative to the asset root, for loading map files as assets
+pub const MAP_ASSET_DIRECTORY: &str = "maps";
+/// Extension used by map files
+pub const MAP_EXTENSION: &str = "map.ron";
+/// Extension of the script shipped alongside a map file
+pub const SCRIPT_EXTENSION: &str = "rhai";
+/// Extension of a map's exported preview image
+pub const PREVIEW_EXTENSION: &str = "preview.png";
+
+/// Player start location placed on the map
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StartLocation {
+    pub player: u32,
+    pub coord: GridCoord,
+}
+
+/// Kinds of harvestable resources
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResourceKind {
+    Gold,
+    Lumber,
+}
+
+impl ResourceKind {
+    /// Color resource markers are drawn in
+    pub fn color(self) -> Color {
+        match self {
+            ResourceKind::Gold => Color::srgb(1.0, 0.85, 0.1),
+            ResourceKind::Lumber => Color::srgb(0.5, 0.3, 0.1),
+        }
+    }
+}
+
+/// Harvestable resource placed on the map
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResourceNode {
+    pub kind: ResourceKind,
+    pub coord: GridCoord,
+    pub amount: u32,
+}
+
+/// Non-terrain objects placed on the current map
+#[derive(Resource, Debug, Clone, Default)]
+pub struct MapObjects {
+    pub start_locations: Vec<StartLocation>,
+    pub resources: Vec<ResourceNode>,
+}
+
+/// On-disk map format
+#[derive(Asset, TypePath, Debug, Clone, Serialize, Deserialize)]
+pub struct MapFile {
+    pub name: String,
+    pub width: i32,
+    pub height: i32,
+    pub cell_size: f32,
+    #[serde(default)]
+    pub topology: GridTopology,
+    /// Cells in row-major order (y * width + x)
+    pub cells: Vec<GridCell>,
+    #[serde(default)]
+    pub start_locations: Vec<StartLocation>,
+    #[serde(default)]
+    pub resources: Vec<ResourceNode>,
+    #[serde(default)]
+    pub regions: Vec<Region>,
+    #[serde(default)]
+    pub triggers: Vec<Trigger>,
+    /// Terrains only this map uses, on top of the shared terrain definitions
+    #[serde(default)]
+    pub terrains: Vec<TerrainDef>,
+}
+
+impl MapFile {
+    /// Path of the map file for a map name
+    pub fn path(map_name: &str) -> PathBuf {
+        PathBuf::from(MAP_DIRECTORY).join(format!("{map_name}.{MAP_EXTENSION}"))
+    }
+
+    /// Asset path of the map file for a map name
+    pub fn asset_path(map_name: &str) -> String {
+        format!("{MAP_ASSET_DIRECTORY}/{map_name}.{MAP_EXTENSION}")
+    }
+
+    /// Path of the script that ships with a map
+    pub fn script_path(map_name: &str) -> PathBuf {
+        PathBuf::from(MAP_DIRECTORY).join(format!("{map_name}.{SCRIPT_EXTENSION}"))
+    }
+
+    /// Asset path of the script that ships with a map
+    pub fn script_asset_path(map_name: &str) -> String {
+        format!("{MAP_ASSET_DIRECTORY}/{map_name}.{SCRIPT_EXTENSION}")
+    }
+
+    /// Path of the preview image exported for a map
+    pub fn preview_path(map_name: &str) -> PathBuf {
+        PathBuf::from(MAP_DIRECTORY).join(format!("{map_name}.{PREVIEW_EXTENSION}"))
+    }
+
+    /// Read and parse a map file
+    pub fn load(map_name: &str) -> Result<Self, String> {
+        let path = Self::path(map_name);
+        let text = std::fs::read_to_string(&path)
+            .map_err(|error| format!("failed to read {}: {error}", path.display()))?;
+        Self::parse(&text).map_err(|error| format!("failed to parse {}: {error}", path.display()))
+    }
+
+    /// Parse map file contents, including maps saved with the old terrain enum
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let map: MapFile = ron::from_str(text)
+            // Report the error for the current format if the map isn't a legacy one either
+            .or_else(|error| read_legacy_terrains(|| ron::from_str(text)).map_err(|_| error))
+            .map_err(|error| error.to_string())?;
+        if map.cells.len() != (map.width * map.height) as usize {
+            return Err(format!(
+                "expected {} cells for a {}x{} map, found {}",
+                map.width * map.height,
+                map.width,
+                map.height,
+                map.cells.len(),
+            ));
+        }
+        Ok(map)
+    }
+
+    /// Write the map file into the map directory
+    pub fn save(&self) -> Result<PathBuf, String> {
+        let path = Self::path(&self.name);
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())?;
+        std::fs::create_dir_all(MAP_DIRECTORY).map_err(|error| error.to_string())?;
+        std::fs::write(&path, text)
+            .map_err(|error| format!("failed to write {}: {error}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Cell at the given coordinates
+    pub fn cell(&self, coord: GridCoord) -> Option<&GridCell> {
+        MapGrid::cell_index(self.width, self.height, coord).and_then(|index| self.cells.get(index))
+    }
+}
+
+/// Asset loader for map files, so the asset server reports edits made to them on disk
+#[derive(Default)]
+pub struct MapFileLoader;
+
+impl AssetLoader for MapFileLoader {
+    type Asset = MapFile;
+    type Settings = ();
+    type Error = String;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<MapFile, String> {
+        let path = load_context.path().display();
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(|error| format!("failed to read {path}: {error}"))?;
+        let text = String::from_utf8(bytes).map_err(|error| format!("failed to read {path}: {error}"))?;
+        MapFile::parse(&text).map_err(|error| format!("failed to parse {path}: {error}"))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &[MAP_EXTENSION]
+    }
+}
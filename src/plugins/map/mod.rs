@@ -1,14 +1,40 @@
 use bevy::prelude::*;
-use crate::plugins::camera::CameraGround;
 
 mod grid;
+mod chunks;
 mod events;
+mod geometry;
+mod hot_reload;
+mod line_of_sight;
 mod loader;
+mod map_file;
+mod overlay;
+mod pathfinding;
+mod preview;
+mod regions;
+mod spatial;
+mod streaming;
+mod terrain;
+mod terrain_mesh;
+mod topology;
 mod unit_examples;
 
-pub use grid::{GridCoord, GridCell, TerrainType, MapGrid};
+pub use grid::{GridCoord, GridCell, GridCells, MapGrid, MAX_CLIFF_LEVEL};
 pub use events::*;
 pub use loader::LoadMapCommand;
+pub use map_file::{MapFile, MapObjects, ResourceKind, ResourceNode, StartLocation, SCRIPT_EXTENSION};
+pub use overlay::{CellOverlay, OverlaySpawner, OverlayStyle};
+pub use preview::{color_bytes, export_preview, DEFAULT_PREVIEW_SCALE};
+pub use regions::{Region, RegionRegistry, RegionShape};
+pub use unit_examples::{spawn_unit, UnitAssets};
+pub use spatial::{GridPosition, OwnershipFilter, SpatialIndex};
+pub use streaming::{ChunkStreaming, Dormant};
+pub use chunks::CellStore;
+pub use terrain::{MovementClass, TerrainRegistry, TerrainType};
+
+/// System set applying `TerrainModifiedEvent`s to grid cells
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TerrainUpdate;
 
 /// Marker component for the terrain mesh
 #[derive(Component)]
@@ -24,26 +50,48 @@ impl Plugin for MapPlugin {
             .add_event::<MapLoadedEvent>()
             .add_event::<TerrainModifiedEvent>()
             .add_event::<UnitMoveEvent>()
+            .add_event::<UnitEnteredRegion>()
+            .add_event::<UnitLeftRegion>()
             .add_event::<BuildingPlacedEvent>()
             .add_event::<TerrainRevealedEvent>()
             .add_event::<PathfindingRequestEvent>()
             .add_event::<PathfindingResultEvent>()
             .add_event::<LoadMapCommand>()
             
+            // Register assets, so map files are reloaded when they change on disk
+            .init_asset::<MapFile>()
+            .init_asset_loader::<map_file::MapFileLoader>()
+            
             // Register resources
             .init_resource::<LoadedMap>()
+            .init_resource::<MapObjects>()
+            .init_resource::<RegionRegistry>()
+            .init_resource::<SpatialIndex>()
+            .init_resource::<ChunkStreaming>()
+            .insert_resource(TerrainRegistry::load())
             
             // Register systems
             .add_systems(Startup, initialize_default_map)
             .add_systems(Update, (
-                handle_terrain_modification,
+                handle_terrain_modification.in_set(TerrainUpdate),
+                terrain_mesh::update_terrain_surface.after(TerrainUpdate),
+                streaming::stream_chunks
+                    .after(loader::handle_load_map_commands)
+                    .before(TerrainUpdate),
+                streaming::sync_cell_store.after(TerrainUpdate),
+                streaming::update_dormant_entities.after(streaming::stream_chunks),
                 handle_pathfinding_requests,
                 loader::handle_load_map_commands,
+                (hot_reload::watch_map_file, hot_reload::hot_reload_map)
+                    .chain()
+                    .after(loader::handle_load_map_commands)
+                    .before(TerrainUpdate),
+                regions::detect_region_transitions,
             ))
-            // Register the grid cells after map initialization
-            .add_systems(PostStartup, register_grid_cells)
             // Spawn example units after the map is loaded
-            .add_systems(PostStartup, unit_examples::spawn_example_units);
+            .add_systems(PostStartup, unit_examples::spawn_example_units)
+            // Index units once this frame's movement is done
+            .add_systems(PostUpdate, spatial::sync_spatial_index);
     }
 }
 
@@ -52,117 +100,91 @@ impl Plugin for MapPlugin {
 pub struct LoadedMap {
     pub name: String,
     pub loaded: bool,
+    /// Map file asset, watched for edits on disk; `None` for generated maps
+    pub file: Option<Handle<MapFile>>,
 }
 
 /// Initialize a default map for testing
 fn initialize_default_map(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut map_loaded_events: EventWriter<MapLoadedEvent>,
-    mut loaded_map: ResMut<LoadedMap>,
+    mut spawner: loader::MapSpawner,
+    mut regions: ResMut<RegionRegistry>,
 ) {
     // Map dimensions
     let width = 32 as i32;
     let height = 32;
     let cell_size = 1.0;
     
-    // Create ground plane mesh
-    let mesh = meshes.add(
-            Plane3d::default()
-            .mesh()
-            .size(cell_size * width as f32, cell_size * width as f32)
-            .subdivisions(width as u32)
-        );
-    
-    // Basic green material for ground
-    let material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.3, 0.5, 0.3),
-        perceptual_roughness: 0.9,
-        ..default()
-    });
+    spawner.spawn_terrain_mesh(&mut commands);
     
-    // Create main terrain entity with camera ground component
-    commands.spawn((
-        Mesh3d(mesh),
-        MeshMaterial3d(material),
-        Transform::from_xyz(
-                (width as f32 * cell_size) / 2.0 - (cell_size / 2.0),
-                0.0,
-                (height as f32 * cell_size) / 2.0 - (cell_size / 2.0),
-        ),
-        TerrainMesh,
-        CameraGround,
-    ));
+    let mut grid = MapGrid::new(width, height, cell_size);
     
-    // Create grid resource
-    commands.insert_resource(MapGrid::new(width, height, cell_size));
-    
-    // Generate grid cells
-    for x in 0..width {
-        for y in 0..height {
-            let coord = GridCoord { x, y };
-            let cell = GridCell::default();
+    // Generate grid cells in row-major order; their entities are spawned as chunks stream in
+    let mut cells = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let mut cell = GridCell::default();
             
-            // Spawn minimal entity for each cell
-            commands.spawn((
-                coord,
-                cell,
-            ));
+            // A raised plateau with a ramp leading up from the south
+            if (20..=26).contains(&x) && (20..=26).contains(&y) {
+                cell.cliff_level = 1;
+            } else if x == 23 && y == 19 {
+                cell.ramp = true;
+            }
             
-            // The MapGrid will be inserted as a resource first, so we'll register cells in a follow-up system
-            // For now we just create the entities
+            cells.push(cell);
         }
     }
+    streaming::install_cells(&mut commands, &mut grid, cells);
+    commands.insert_resource(grid);
     
-    // Send map loaded event
-    map_loaded_events.write(MapLoadedEvent {
-        map_name: "default".to_string(),
-        width,
-        height,
-    });
+    // Named areas used by the example units
+    regions.set_regions(vec![
+        Region {
+            name: "player_spawn".to_string(),
+            shape: RegionShape::Cells(vec![
+                GridCoord { x: 10, y: 10 },
+                GridCoord { x: 12, y: 10 },
+                GridCoord { x: 10, y: 12 },
+            ]),
+        },
+        Region::rect("target", GridCoord { x: 15, y: 15 }, GridCoord { x: 15, y: 15 }),
+    ]);
     
-    // Update loaded map resource
-    loaded_map.name = "default".to_string();
-    loaded_map.loaded = true;
+    spawner.finish("default", width, height);
 }
 
 /// Handle terrain modification events
 fn handle_terrain_modification(
     mut events: EventReader<TerrainModifiedEvent>,
     mut grid_cells: Query<&mut GridCell>,
-    grid_coords: Query<(Entity, &GridCoord)>,
+    grid: Res<MapGrid>,
+    terrains: Res<TerrainRegistry>,
 ) {
     for event in events.read() {
-        // Find the entity with the matching grid coordinate
-        for (entity, coord) in grid_coords.iter() {
-            if *coord == event.coord {
-                // Update the cell's terrain type
-                if let Ok(mut cell) = grid_cells.get_mut(entity) {
-                    cell.terrain = event.new_terrain;
-                    
-                    // Additional logic for terrain changes
-                    match event.new_terrain {
-                        TerrainType::Water => {
-                            cell.walkable = false;
-                            cell.buildable = false;
-                        },
-                        TerrainType::Mountain => {
-                            cell.walkable = false; 
-                            cell.buildable = false;
-                            cell.elevation = 2.0;
-                        },
-                        TerrainType::Forest => {
-                            cell.buildable = false;
-                        },
-                        _ => {
-                            // Default for other terrain types
-                            cell.walkable = true;
-                            cell.buildable = true;
-                        }
-                    }
-                }
-                break;
+        let Some(entity) = grid.get_cell_entity(event.coord) else {
+            continue;
+        };
+        // Switch to the new terrain with its default properties
+        if let Ok(mut cell) = grid_cells.get_mut(*entity) {
+            cell.terrain = event.new_terrain.clone();
+            terrains.get(&cell.terrain).apply_defaults(&mut cell);
+
+            // Explicit overrides win over the terrain defaults
+            if let Some(elevation) = event.elevation {
+                cell.elevation = elevation;
+            }
+            if let Some(cliff_level) = event.cliff_level {
+                cell.cliff_level = cliff_level;
+            }
+            if let Some(ramp) = event.ramp {
+                cell.ramp = ramp;
+            }
+            if let Some(walkable) = event.walkable {
+                cell.walkable = walkable;
+            }
+            if let Some(buildable) = event.buildable {
+                cell.buildable = buildable;
             }
         }
     }
@@ -172,46 +194,27 @@ fn handle_terrain_modification(
 fn handle_pathfinding_requests(
     mut request_events: EventReader<PathfindingRequestEvent>,
     mut result_events: EventWriter<PathfindingResultEvent>,
-    grid: Res<MapGrid>,
-    _grid_cells: Query<&GridCell>,
-    _grid_coords: Query<(Entity, &GridCoord)>,
+    cells: GridCells,
     time: Res<Time>,
 ) {
     for event in request_events.read() {
-        // Basic pathfinding implementation
-        // For now, just return a direct path if possible
-        // In the future, implement A* or other pathfinding algorithms
-        
-        if !grid.in_bounds(event.from) || !grid.in_bounds(event.to) {
-            // Invalid coordinates
-            result_events.write(PathfindingResultEvent {
-                entity: event.entity,
-                path: Vec::new(),
-                success: false,
-                timestamp: time.elapsed_secs_f64(),
-            });
-            continue;
+        // A* over cells the unit's movement class can cross, moving between cliff levels
+        // only over ramps. A route that may cross unloaded chunks falls back to a coarse
+        // path over chunk summaries.
+        let both_loaded = cells.grid.is_loaded(event.from) && cells.grid.is_loaded(event.to);
+        let mut path = both_loaded
+            .then(|| cells.find_path_for(event.from, event.to, event.movement))
+            .flatten();
+        if path.is_none() && !cells.grid.all_chunks_loaded() {
+            path = cells.grid.find_coarse_path(event.from, event.to);
         }
         
-        // TODO: Implement actual pathfinding
-        // For now, just return a direct path
-        let path = vec![event.from, event.to];
-        
         result_events.write(PathfindingResultEvent {
             entity: event.entity,
-            path,
-            success: true,
+            request: event.request,
+            success: path.is_some(),
+            path: path.unwrap_or_default(),
             timestamp: time.elapsed_secs_f64(),
         });
     }
 }
-
-/// System to register cell entities with grid (runs once after initialization)
-pub fn register_grid_cells(
-    mut grid: ResMut<MapGrid>,
-    grid_entities: Query<(Entity, &GridCoord)>,
-) {
-    for (entity, coord) in grid_entities.iter() {
-        grid.register_cell(*coord, entity);
-    }
-}
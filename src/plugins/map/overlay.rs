@@ -0,0 +1,107 @@
+use bevy::ecs::system::SystemParam;
+use bevy::image::ImageSampler;
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use super::grid::{GridCoord, MapGrid};
+use super::TerrainMesh;
+
+/// Look of a cell overlay
+pub struct OverlayStyle {
+    /// Color of every cell; only the opacity differs between cells
+    pub color: [u8; 3],
+    /// Opacity cells start out with
+    pub opacity: f32,
+    /// Height above the ground, keeping overlays stacked in order
+    pub height: f32,
+    /// Depth bias drawing the overlay over the terrain surface it shares a mesh with
+    pub depth_bias: f32,
+}
+
+/// Texture with one texel per cell draped over the terrain, whose cells fade towards
+/// the opacity they should have
+pub struct CellOverlay {
+    pub image: Handle<Image>,
+    width: i32,
+    opacity: Vec<f32>,
+}
+
+impl CellOverlay {
+    /// Move each cell's opacity `blend` (0 to 1) of the way towards `target` and write it
+    /// into the texture
+    pub fn fade(&mut self, images: &mut Assets<Image>, blend: f32, target: impl Fn(GridCoord) -> f32) {
+        let Some(image) = images.get_mut(&self.image) else {
+            return;
+        };
+        let Some(data) = image.data.as_mut() else {
+            return;
+        };
+
+        for (index, opacity) in self.opacity.iter_mut().enumerate() {
+            let coord = GridCoord { x: index as i32 % self.width, y: index as i32 / self.width };
+            *opacity += (target(coord) - *opacity) * blend;
+            data[index * 4 + 3] = (*opacity * 255.0) as u8;
+        }
+    }
+}
+
+/// System parameter spawning cell overlays over the terrain
+#[derive(SystemParam)]
+pub struct OverlaySpawner<'w, 's> {
+    pub commands: Commands<'w, 's>,
+    pub grid: Res<'w, MapGrid>,
+    pub materials: ResMut<'w, Assets<StandardMaterial>>,
+    images: ResMut<'w, Assets<Image>>,
+    terrain: Query<'w, 's, &'static Mesh3d, With<TerrainMesh>>,
+}
+
+impl OverlaySpawner<'_, '_> {
+    /// Spawn an overlay for the current map along with `bundle`, or `None` while there is
+    /// no terrain mesh to drape it over
+    pub fn spawn(&mut self, style: &OverlayStyle, bundle: impl Bundle) -> Option<CellOverlay> {
+        // The overlay is draped over the terrain by sharing its mesh
+        let terrain_mesh = self.terrain.iter().next()?.0.clone();
+
+        let [r, g, b] = style.color;
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: self.grid.width as u32,
+                height: self.grid.height as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[r, g, b, (style.opacity * 255.0) as u8],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        );
+        // Linear filtering blends neighboring cells into soft edges
+        image.sampler = ImageSampler::linear();
+        let image = self.images.add(image);
+
+        // Terrain UVs span the whole map, so each cell samples its own texel
+        let material = self.materials.add(StandardMaterial {
+            base_color: Color::WHITE,
+            base_color_texture: Some(image.clone()),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            // Keeps the overlay in front of cliff faces, which the lift doesn't separate
+            depth_bias: style.depth_bias,
+            ..default()
+        });
+
+        self.commands.spawn((
+            Mesh3d(terrain_mesh),
+            MeshMaterial3d(material),
+            Transform::from_xyz(0.0, style.height, 0.0),
+            NotShadowCaster,
+            bundle,
+        ));
+
+        Some(CellOverlay {
+            image,
+            width: self.grid.width,
+            opacity: vec![style.opacity; (self.grid.width * self.grid.height) as usize],
+        })
+    }
+}
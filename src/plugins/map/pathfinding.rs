@@ -0,0 +1,163 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use super::grid::{GridCoord, GridCell, GridCells};
+use super::terrain::MovementClass;
+use super::topology::GridTopology;
+
+/// Most cells a single search may expand, so unreachable targets stay cheap on big maps
+const MAX_EXPANDED: usize = 20_000;
+
+/// Whether two cells' cliff levels are connected: equal, or one apart over a ramp
+pub(super) fn levels_connected(a: &GridCell, b: &GridCell) -> bool {
+    match (a.cliff_level - b.cliff_level).abs() {
+        0 => true,
+        1 => a.ramp || b.ramp,
+        _ => false,
+    }
+}
+
+/// Shortest possible walk between two cells on open ground
+fn walk_distance(topology: GridTopology, a: GridCoord, b: GridCoord) -> f32 {
+    match topology {
+        // Diagonal moves are allowed
+        GridTopology::Square => a.octile_distance(b),
+        GridTopology::Hex => topology.distance(a, b),
+    }
+}
+
+/// Open list entry, ordered so the cheapest estimate pops first
+struct OpenNode {
+    estimate: f32,
+    coord: GridCoord,
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate.total_cmp(&other.estimate) == Ordering::Equal
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+impl GridCells<'_, '_> {
+    /// Check if units of a movement class can cross a cell. Ground units follow the
+    /// cell's own walkable flag, which may differ from its terrain's default.
+    pub fn is_walkable_for(&self, cell: &GridCell, class: MovementClass) -> bool {
+        match class {
+            MovementClass::Ground => cell.walkable,
+            _ => self.terrains.get(&cell.terrain).is_walkable_for(class),
+        }
+    }
+
+    /// Check if a unit can step straight (not diagonally) between neighboring cells
+    fn can_step_straight(&self, from: GridCoord, to: GridCoord, class: MovementClass) -> bool {
+        match (self.get(from), self.get(to)) {
+            (Some(from), Some(to)) => self.is_walkable_for(to, class) && levels_connected(from, to),
+            _ => false,
+        }
+    }
+
+    /// Check if a ground unit can step from a cell into one of its neighbors
+    pub fn can_step(&self, from: GridCoord, to: GridCoord) -> bool {
+        self.can_step_for(from, to, MovementClass::Ground)
+    }
+
+    /// Check if a unit can step from a cell into one of its neighbors.
+    ///
+    /// The target must be crossable by the movement class and on a connected cliff level.
+    /// Diagonal steps on square grids must also be possible around both corners, so units
+    /// can't cut past cliff edges.
+    pub fn can_step_for(&self, from: GridCoord, to: GridCoord, class: MovementClass) -> bool {
+        if !self.grid.neighbors(from).contains(&to) {
+            return false;
+        }
+        if self.grid.topology == GridTopology::Hex || from.x == to.x || from.y == to.y {
+            return self.can_step_straight(from, to, class);
+        }
+
+        let corner_x = GridCoord { x: to.x, y: from.y };
+        let corner_y = GridCoord { x: from.x, y: to.y };
+        self.can_step_straight(from, corner_x, class)
+            && self.can_step_straight(corner_x, to, class)
+            && self.can_step_straight(from, corner_y, class)
+            && self.can_step_straight(corner_y, to, class)
+    }
+
+    /// Cheapest path for a ground unit between two cells, both ends included
+    pub fn find_path(&self, from: GridCoord, to: GridCoord) -> Option<Vec<GridCoord>> {
+        self.find_path_for(from, to, MovementClass::Ground)
+    }
+
+    /// Cheapest path between two cells (A*) for a movement class, both ends included.
+    /// Each step costs its length times the move cost of the terrain stepped onto.
+    /// Works on any grid topology.
+    pub fn find_path_for(&self, from: GridCoord, to: GridCoord, class: MovementClass) -> Option<Vec<GridCoord>> {
+        if self.get(from).is_none() || !self.get(to).is_some_and(|cell| self.is_walkable_for(cell, class)) {
+            return None;
+        }
+
+        // Scale the estimate by the cheapest terrain so it never overshoots
+        let min_cost = self.terrains.min_move_cost();
+        let heuristic = |coord: GridCoord| walk_distance(self.grid.topology, coord, to) * min_cost;
+
+        let mut open = BinaryHeap::new();
+        let mut cost: HashMap<GridCoord, f32> = HashMap::new();
+        let mut came_from: HashMap<GridCoord, GridCoord> = HashMap::new();
+        open.push(OpenNode { estimate: heuristic(from), coord: from });
+        cost.insert(from, 0.0);
+
+        let mut expanded = 0;
+        while let Some(OpenNode { estimate, coord }) = open.pop() {
+            if coord == to {
+                let mut path = vec![to];
+                let mut current = to;
+                while let Some(previous) = came_from.get(&current) {
+                    path.push(*previous);
+                    current = *previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let current_cost = cost[&coord];
+            // Skip stale entries left behind when a cheaper route was found
+            if estimate > current_cost + heuristic(coord) + f32::EPSILON {
+                continue;
+            }
+            expanded += 1;
+            if expanded > MAX_EXPANDED {
+                return None;
+            }
+
+            for next in self.grid.neighbors(coord) {
+                if !self.can_step_for(coord, next, class) {
+                    continue;
+                }
+                let Some(next_cell) = self.get(next) else {
+                    continue;
+                };
+                let step = self.grid.distance(coord, next);
+                let next_cost = current_cost + step * self.terrains.get(&next_cell.terrain).move_cost;
+                if cost.get(&next).is_some_and(|known| *known <= next_cost) {
+                    continue;
+                }
+                cost.insert(next, next_cost);
+                came_from.insert(next, coord);
+                open.push(OpenNode { estimate: next_cost + heuristic(next), coord: next });
+            }
+        }
+        None
+    }
+}
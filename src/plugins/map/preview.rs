@@ -0,0 +1,102 @@
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use std::path::PathBuf;
+use crate::components::faction::FactionId;
+use super::grid::GridCoord;
+use super::map_file::MapFile;
+use super::terrain::TerrainRegistry;
+
+/// Preview pixels per grid cell unless asked otherwise
+pub const DEFAULT_PREVIEW_SCALE: u32 = 4;
+/// Brightness of the map's lowest ground; the highest ground is drawn at full brightness
+const LOW_GROUND_SHADE: f32 = 0.55;
+const OUTLINE_COLOR: [u8; 4] = [255, 255, 255, 255];
+
+/// Draw a top-down preview of a map on the CPU: terrain colors shaded by height, with
+/// start locations and resources marked. Each cell becomes a `scale` by `scale` block
+/// at its grid coordinates, so hex maps come out sheared.
+pub fn render_preview(map: &MapFile, terrains: &TerrainRegistry, scale: u32) -> Image {
+    let scale = scale.max(1) as i32;
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: (map.width * scale) as u32,
+            height: (map.height * scale) as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    let image_width = map.width * scale;
+    let image_height = map.height * scale;
+    let data = image.data.get_or_insert_default();
+    let mut fill_rect = |x: i32, y: i32, width: i32, height: i32, color: [u8; 4]| {
+        for py in y.max(0)..(y + height).min(image_height) {
+            for px in x.max(0)..(x + width).min(image_width) {
+                let index = ((py * image_width + px) * 4) as usize;
+                data[index..index + 4].copy_from_slice(&color);
+            }
+        }
+    };
+
+    let (lowest, highest) = map
+        .cells
+        .iter()
+        .map(|cell| cell.height())
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), height| (low.min(height), high.max(height)));
+    let height_range = (highest - lowest).max(f32::EPSILON);
+
+    for y in 0..map.height {
+        for x in 0..map.width {
+            let Some(cell) = map.cell(GridCoord { x, y }) else {
+                continue;
+            };
+            let relative = (cell.height() - lowest) / height_range;
+            let shade = LOW_GROUND_SHADE + (1.0 - LOW_GROUND_SHADE) * relative;
+            let color = color_bytes(terrains.get(&cell.terrain).color(), shade);
+            fill_rect(x * scale, y * scale, scale, scale, color);
+        }
+    }
+
+    // Markers are centered on their cell and stay visible at small scales
+    let mut marker = |coord: GridCoord, size: i32, color: Color| {
+        let center_x = coord.x * scale + scale / 2;
+        let center_y = coord.y * scale + scale / 2;
+        let outer = size + 2;
+        fill_rect(center_x - outer / 2, center_y - outer / 2, outer, outer, OUTLINE_COLOR);
+        fill_rect(center_x - size / 2, center_y - size / 2, size, size, color_bytes(color, 1.0));
+    };
+    for node in &map.resources {
+        marker(node.coord, scale.max(3), node.kind.color());
+    }
+    for start in &map.start_locations {
+        marker(start.coord, (scale * 2).max(5), FactionId::Player(start.player).color());
+    }
+
+    image
+}
+
+/// Render a map file's preview with the shared and map terrains and write it as a PNG
+/// next to the map
+pub fn export_preview(map_name: &str, scale: u32) -> Result<PathBuf, String> {
+    let map = MapFile::load(map_name)?;
+    let mut terrains = TerrainRegistry::load();
+    terrains.set_map_terrains(&map.terrains);
+
+    let path = MapFile::preview_path(map_name);
+    render_preview(&map, &terrains, scale)
+        .try_into_dynamic()
+        .map_err(|error| error.to_string())?
+        .save(&path)
+        .map_err(|error| format!("failed to write {}: {error}", path.display()))?;
+    Ok(path)
+}
+
+/// Opaque RGBA pixel of a color darkened by `shade` (1.0 keeps it as it is), for images
+/// drawn on the CPU such as previews and the minimap
+pub fn color_bytes(color: Color, shade: f32) -> [u8; 4] {
+    let srgba = color.to_srgba();
+    Srgba::new(srgba.red * shade, srgba.green * shade, srgba.blue * shade, 1.0).to_u8_array()
+}
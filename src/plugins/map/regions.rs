@@ -0,0 +1,148 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use super::grid::GridCoord;
+use super::events::{UnitMoveEvent, UnitEnteredRegion, UnitLeftRegion};
+
+/// Area covered by a region
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RegionShape {
+    /// Rectangle between two corner cells (inclusive)
+    Rect { min: GridCoord, max: GridCoord },
+    /// Arbitrary set of cells
+    Cells(Vec<GridCoord>),
+}
+
+/// Named area of the map used for spawns, waypoints and objectives
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub name: String,
+    pub shape: RegionShape,
+}
+
+impl Region {
+    /// Rectangular region spanning two corner cells
+    pub fn rect(name: &str, a: GridCoord, b: GridCoord) -> Self {
+        Self {
+            name: name.to_string(),
+            shape: RegionShape::Rect {
+                min: GridCoord { x: a.x.min(b.x), y: a.y.min(b.y) },
+                max: GridCoord { x: a.x.max(b.x), y: a.y.max(b.y) },
+            },
+        }
+    }
+
+    /// Check if a cell lies inside the region
+    pub fn contains(&self, coord: GridCoord) -> bool {
+        match &self.shape {
+            RegionShape::Rect { min, max } => {
+                coord.x >= min.x && coord.x <= max.x && coord.y >= min.y && coord.y <= max.y
+            }
+            RegionShape::Cells(cells) => cells.contains(&coord),
+        }
+    }
+
+    /// Cell closest to the middle of the region
+    pub fn center(&self) -> Option<GridCoord> {
+        match &self.shape {
+            RegionShape::Rect { min, max } => Some(GridCoord {
+                x: (min.x + max.x) / 2,
+                y: (min.y + max.y) / 2,
+            }),
+            RegionShape::Cells(cells) => {
+                let count = cells.len() as i32;
+                if count == 0 {
+                    return None;
+                }
+                let sum = cells.iter().fold((0, 0), |sum, cell| (sum.0 + cell.x, sum.1 + cell.y));
+                let average = GridCoord { x: sum.0 / count, y: sum.1 / count };
+                cells.iter().copied().min_by_key(|cell| {
+                    (cell.x - average.x).pow(2) + (cell.y - average.y).pow(2)
+                })
+            }
+        }
+    }
+
+    /// Every cell in the region
+    pub fn cells(&self) -> Vec<GridCoord> {
+        match &self.shape {
+            RegionShape::Rect { min, max } => min.rect_to(*max).collect(),
+            RegionShape::Cells(cells) => cells.clone(),
+        }
+    }
+}
+
+/// Resource holding the named regions of the current map
+#[derive(Resource, Debug, Default)]
+pub struct RegionRegistry {
+    regions: Vec<Region>,
+    by_name: HashMap<String, usize>,
+}
+
+impl RegionRegistry {
+    /// Replace all regions
+    pub fn set_regions(&mut self, regions: Vec<Region>) {
+        self.regions.clear();
+        self.by_name.clear();
+        for region in regions {
+            self.insert(region);
+        }
+    }
+
+    /// Add a region, replacing any region with the same name
+    pub fn insert(&mut self, region: Region) {
+        match self.by_name.get(&region.name) {
+            Some(index) => self.regions[*index] = region,
+            None => {
+                self.by_name.insert(region.name.clone(), self.regions.len());
+                self.regions.push(region);
+            }
+        }
+    }
+
+    /// Look up a region by name
+    pub fn get(&self, name: &str) -> Option<&Region> {
+        self.by_name.get(name).map(|index| &self.regions[*index])
+    }
+
+    /// All regions, in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = &Region> {
+        self.regions.iter()
+    }
+
+    /// Regions containing a cell
+    pub fn regions_at(&self, coord: GridCoord) -> impl Iterator<Item = &Region> {
+        self.regions.iter().filter(move |region| region.contains(coord))
+    }
+}
+
+/// Turn unit moves into region enter/leave events
+pub fn detect_region_transitions(
+    mut move_events: EventReader<UnitMoveEvent>,
+    registry: Res<RegionRegistry>,
+    mut entered_events: EventWriter<UnitEnteredRegion>,
+    mut left_events: EventWriter<UnitLeftRegion>,
+) {
+    for event in move_events.read() {
+        for region in registry.iter() {
+            let was_inside = region.contains(event.from);
+            let is_inside = region.contains(event.to);
+
+            if is_inside && !was_inside {
+                entered_events.write(UnitEnteredRegion {
+                    entity: event.entity,
+                    region: region.name.clone(),
+                    coord: event.to,
+                    timestamp: event.timestamp,
+                });
+            } else if was_inside && !is_inside {
+                left_events.write(UnitLeftRegion {
+                    entity: event.entity,
+                    region: region.name.clone(),
+                    coord: event.to,
+                    timestamp: event.timestamp,
+                });
+            }
+        }
+    }
+}
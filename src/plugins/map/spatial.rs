@@ -0,0 +1,393 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+use crate::components::unit::Unit;
+use crate::components::faction::{Ownership, FactionId};
+use super::grid::{GridCoord, MapGrid};
+
+/// Grid cell currently occupied by a unit, kept in sync with its Transform
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridPosition(pub GridCoord);
+
+/// Filter applied to spatial queries based on unit ownership
+#[derive(Debug, Clone, Default)]
+pub enum OwnershipFilter {
+    #[default]
+    Any,
+    Faction(FactionId),
+    AlliedWith(Ownership),
+    HostileTo(Ownership),
+}
+
+impl OwnershipFilter {
+    /// Check whether the given ownership passes the filter
+    pub fn matches(&self, ownership: &Ownership) -> bool {
+        match self {
+            OwnershipFilter::Any => true,
+            OwnershipFilter::Faction(faction) => ownership.faction == *faction,
+            OwnershipFilter::AlliedWith(other) => ownership.is_allied_with(other),
+            OwnershipFilter::HostileTo(other) => ownership.is_hostile_to(other),
+        }
+    }
+}
+
+/// Indexed data for a single unit
+#[derive(Debug, Clone)]
+struct SpatialEntry {
+    entity: Entity,
+    position: Vec3,
+    ownership: Ownership,
+    /// Bucket the unit is filed under
+    bucket: GridCoord,
+}
+
+/// Resource bucketing units into square cells for proximity queries. Buckets are
+/// cell-sized squares on the XZ plane whatever the map's topology; the map cell a unit
+/// stands on is its `GridPosition`.
+#[derive(Resource, Default)]
+pub struct SpatialIndex {
+    cell_size: f32,
+    /// Units contained in each occupied bucket
+    buckets: HashMap<GridCoord, Vec<Entity>>,
+    entries: HashMap<Entity, SpatialEntry>,
+}
+
+impl SpatialIndex {
+    /// Insert or update a unit in the index
+    pub fn update(&mut self, entity: Entity, position: Vec3, ownership: &Ownership) {
+        let bucket = self.coord_of(position);
+        if let Some(entry) = self.entries.get_mut(&entity) {
+            if entry.bucket != bucket {
+                let old = entry.bucket;
+                entry.bucket = bucket;
+                Self::remove_from_bucket(&mut self.buckets, old, entity);
+                self.buckets.entry(bucket).or_default().push(entity);
+            }
+            entry.position = position;
+            entry.ownership = ownership.clone();
+            return;
+        }
+
+        self.buckets.entry(bucket).or_default().push(entity);
+        self.entries.insert(entity, SpatialEntry {
+            entity,
+            position,
+            ownership: ownership.clone(),
+            bucket,
+        });
+    }
+
+    /// Remove a unit from the index
+    pub fn remove(&mut self, entity: Entity) {
+        if let Some(entry) = self.entries.remove(&entity) {
+            Self::remove_from_bucket(&mut self.buckets, entry.bucket, entity);
+        }
+    }
+
+    /// Drop all entries and switch to a new cell size
+    pub fn clear(&mut self, cell_size: f32) {
+        self.cell_size = cell_size;
+        self.buckets.clear();
+        self.entries.clear();
+    }
+
+    /// Units within `radius` world units of `center` (measured on the XZ plane)
+    pub fn query_radius(&self, center: Vec3, radius: f32, filter: &OwnershipFilter) -> Vec<Entity> {
+        let min = self.coord_of(center - Vec3::new(radius, 0.0, radius));
+        let max = self.coord_of(center + Vec3::new(radius, 0.0, radius));
+        let radius_sq = radius * radius;
+
+        self.entries_in_rect(min, max)
+            .filter(|entry| planar_distance_sq(entry.position, center) <= radius_sq)
+            .filter(|entry| filter.matches(&entry.ownership))
+            .map(|entry| entry.entity)
+            .collect()
+    }
+
+    /// Units inside the rectangle spanned by two corners (on the XZ plane, edges included)
+    pub fn query_rect(&self, a: Vec3, b: Vec3, filter: &OwnershipFilter) -> Vec<Entity> {
+        let (min, max) = (a.min(b), a.max(b));
+
+        self.entries_in_rect(self.coord_of(min), self.coord_of(max))
+            .filter(|entry| {
+                let position = entry.position;
+                (min.x..=max.x).contains(&position.x) && (min.z..=max.z).contains(&position.z)
+            })
+            .filter(|entry| filter.matches(&entry.ownership))
+            .map(|entry| entry.entity)
+            .collect()
+    }
+
+    /// Up to `count` units closest to `center`, nearest first, searching at most `max_radius`
+    pub fn nearest(&self, center: Vec3, count: usize, max_radius: f32, filter: &OwnershipFilter) -> Vec<Entity> {
+        if count == 0 || self.entries.is_empty() || self.cell_size <= 0.0 {
+            return Vec::new();
+        }
+
+        let origin = self.coord_of(center);
+        let max_ring = (max_radius / self.cell_size).ceil() as i32;
+        let max_radius_sq = max_radius * max_radius;
+        let mut found: Vec<(f32, Entity)> = Vec::new();
+        let mut visited = 0;
+
+        // Search outward ring by ring, stopping once no unvisited cell can hold a closer unit
+        for ring in 0..=max_ring {
+            for coord in origin.square_ring(ring) {
+                for entity in self.bucket(coord) {
+                    visited += 1;
+                    let entry = &self.entries[entity];
+                    let distance_sq = planar_distance_sq(entry.position, center);
+                    if distance_sq <= max_radius_sq && filter.matches(&entry.ownership) {
+                        found.push((distance_sq, *entity));
+                    }
+                }
+            }
+
+            // Every indexed unit has been seen, nothing further out
+            if visited == self.entries.len() {
+                break;
+            }
+
+            if found.len() >= count {
+                found.sort_by(|a, b| a.0.total_cmp(&b.0));
+                let reach = ring as f32 * self.cell_size;
+                if found[count - 1].0 <= reach * reach {
+                    break;
+                }
+            }
+        }
+
+        found.sort_by(|a, b| a.0.total_cmp(&b.0));
+        found.truncate(count);
+        found.into_iter().map(|(_, entity)| entity).collect()
+    }
+
+    /// Units filed under a bucket
+    fn bucket(&self, coord: GridCoord) -> &[Entity] {
+        self.buckets.get(&coord).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Bucket containing a world position
+    fn coord_of(&self, position: Vec3) -> GridCoord {
+        GridCoord {
+            x: (position.x / self.cell_size).floor() as i32,
+            y: (position.z / self.cell_size).floor() as i32,
+        }
+    }
+
+    fn entries_in_rect(&self, min: GridCoord, max: GridCoord) -> impl Iterator<Item = &SpatialEntry> {
+        min.rect_to(max)
+            .flat_map(|coord| self.bucket(coord))
+            .map(|entity| &self.entries[entity])
+    }
+
+    fn remove_from_bucket(buckets: &mut HashMap<GridCoord, Vec<Entity>>, coord: GridCoord, entity: Entity) {
+        if let Some(bucket) = buckets.get_mut(&coord) {
+            bucket.retain(|e| *e != entity);
+            if bucket.is_empty() {
+                buckets.remove(&coord);
+            }
+        }
+    }
+}
+
+fn planar_distance_sq(a: Vec3, b: Vec3) -> f32 {
+    Vec2::new(a.x - b.x, a.z - b.z).length_squared()
+}
+
+/// What the index tracks of each unit
+type IndexedUnit<'a> = (Entity, Ref<'a, Transform>, Ref<'a, Ownership>, Option<&'a mut GridPosition>);
+
+/// Keep the spatial index and unit grid positions in sync with unit transforms
+pub fn sync_spatial_index(
+    mut commands: Commands,
+    mut index: ResMut<SpatialIndex>,
+    grid: Res<MapGrid>,
+    mut units: Query<IndexedUnit, With<Unit>>,
+    mut removed: RemovedComponents<Unit>,
+) {
+    for entity in removed.read() {
+        index.remove(entity);
+    }
+
+    // A new grid may use a different cell size, so re-index everything
+    let rebuild = grid.is_changed();
+    if rebuild {
+        index.clear(grid.cell_size);
+    }
+
+    for (entity, transform, ownership, grid_position) in units.iter_mut() {
+        let moved = transform.is_changed() || ownership.is_changed();
+        if !rebuild && !moved && grid_position.is_some() {
+            continue;
+        }
+
+        index.update(entity, transform.translation, &ownership);
+
+        let coord = grid.world_to_grid(transform.translation);
+        match grid_position {
+            Some(mut position) => {
+                if position.0 != coord {
+                    position.0 = coord;
+                }
+            }
+            None => {
+                commands.entity(entity).insert(GridPosition(coord));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::faction::TeamId;
+    use proptest::prelude::*;
+
+    fn ownership(player: u32, team: u32) -> Ownership {
+        Ownership {
+            faction: FactionId::Player(player),
+            team: TeamId::Team(team),
+            ..default()
+        }
+    }
+
+    /// Index holding one unit per position, entity ids counting up from zero
+    fn index_of(units: &[(Vec3, Ownership)]) -> SpatialIndex {
+        let mut index = SpatialIndex::default();
+        index.clear(1.0);
+        for (id, (position, ownership)) in units.iter().enumerate() {
+            index.update(Entity::from_raw(id as u32), *position, ownership);
+        }
+        index
+    }
+
+    fn ids(mut entities: Vec<Entity>) -> Vec<u32> {
+        entities.sort();
+        entities.into_iter().map(Entity::index).collect()
+    }
+
+    #[test]
+    fn radius_query_keeps_units_in_range_that_pass_the_filter() {
+        let index = index_of(&[
+            (Vec3::new(5.0, 0.0, 5.0), ownership(1, 1)),
+            (Vec3::new(6.5, 0.0, 5.0), ownership(2, 2)),
+            (Vec3::new(5.0, 3.0, 6.9), ownership(2, 2)),
+            (Vec3::new(7.5, 0.0, 5.0), ownership(2, 2)),
+            (Vec3::new(5.5, 0.0, 5.5), ownership(3, 1)),
+        ]);
+        let center = Vec3::new(5.0, 0.0, 5.0);
+
+        assert_eq!(ids(index.query_radius(center, 2.0, &OwnershipFilter::Any)), [0, 1, 2, 4]);
+        let hostile = OwnershipFilter::HostileTo(ownership(1, 1));
+        assert_eq!(ids(index.query_radius(center, 2.0, &hostile)), [1, 2]);
+        let allied = OwnershipFilter::AlliedWith(ownership(1, 1));
+        assert_eq!(ids(index.query_radius(center, 2.0, &allied)), [0, 4]);
+    }
+
+    #[test]
+    fn rect_query_keeps_units_inside_the_corners() {
+        let index = index_of(&[
+            (Vec3::new(1.0, 0.0, 1.0), ownership(1, 1)),
+            (Vec3::new(2.5, 0.0, 3.0), ownership(1, 1)),
+            (Vec3::new(3.0, 0.0, 3.2), ownership(1, 1)),
+            (Vec3::new(2.0, 0.0, 2.0), ownership(2, 2)),
+        ]);
+        // Corners in either order, and a unit on the edge counts
+        let (a, b) = (Vec3::new(3.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 3.0));
+
+        assert_eq!(ids(index.query_rect(a, b, &OwnershipFilter::Any)), [0, 1, 3]);
+        let own = OwnershipFilter::Faction(FactionId::Player(1));
+        assert_eq!(ids(index.query_rect(a, b, &own)), [0, 1]);
+    }
+
+    #[test]
+    fn nearest_orders_by_distance_and_filters() {
+        let index = index_of(&[
+            (Vec3::new(3.0, 0.0, 0.0), ownership(2, 2)),
+            (Vec3::new(1.0, 0.0, 0.0), ownership(1, 1)),
+            (Vec3::new(2.0, 0.0, 0.0), ownership(2, 2)),
+            (Vec3::new(9.0, 0.0, 0.0), ownership(2, 2)),
+        ]);
+        let hostile = OwnershipFilter::HostileTo(ownership(1, 1));
+
+        let nearest = index.nearest(Vec3::ZERO, 2, 5.0, &OwnershipFilter::Any);
+        assert_eq!(nearest, [Entity::from_raw(1), Entity::from_raw(2)]);
+        let nearest = index.nearest(Vec3::ZERO, 5, 5.0, &hostile);
+        assert_eq!(nearest, [Entity::from_raw(2), Entity::from_raw(0)]);
+    }
+
+    #[test]
+    fn nearest_keeps_searching_past_a_ring_with_a_far_corner() {
+        // The diagonal unit sits in the first ring but further away than the unit two
+        // rings out, so the search must not stop after the first ring
+        let index = index_of(&[
+            (Vec3::new(1.95, 0.0, 1.95), ownership(1, 1)),
+            (Vec3::new(2.1, 0.0, 0.5), ownership(1, 1)),
+        ]);
+        let nearest = index.nearest(Vec3::new(0.5, 0.0, 0.5), 1, 10.0, &OwnershipFilter::Any);
+        assert_eq!(nearest, [Entity::from_raw(1)]);
+    }
+
+    #[test]
+    fn moved_and_removed_units_leave_their_old_bucket() {
+        let mut index = index_of(&[(Vec3::new(0.5, 0.0, 0.5), ownership(1, 1))]);
+        let unit = Entity::from_raw(0);
+
+        index.update(unit, Vec3::new(4.5, 0.0, 4.5), &ownership(1, 1));
+        assert!(index.query_radius(Vec3::ZERO, 1.0, &OwnershipFilter::Any).is_empty());
+        assert_eq!(index.query_radius(Vec3::new(4.5, 0.0, 4.5), 1.0, &OwnershipFilter::Any), [unit]);
+
+        index.remove(unit);
+        assert!(index.nearest(Vec3::ZERO, 1, 10.0, &OwnershipFilter::Any).is_empty());
+    }
+
+    fn units() -> impl Strategy<Value = Vec<(Vec3, Ownership)>> {
+        let unit = (-20.0f32..20.0, -20.0f32..20.0, 1u32..4)
+            .prop_map(|(x, z, player)| (Vec3::new(x, 0.0, z), ownership(player, player)));
+        prop::collection::vec(unit, 0..40)
+    }
+
+    proptest! {
+        #[test]
+        fn nearest_matches_a_full_scan(
+            units in units(),
+            x in -20.0f32..20.0,
+            z in -20.0f32..20.0,
+            count in 1usize..6,
+            max_radius in 0.0f32..30.0,
+        ) {
+            let index = index_of(&units);
+            let center = Vec3::new(x, 0.0, z);
+            let filter = OwnershipFilter::HostileTo(ownership(1, 1));
+
+            let mut expected: Vec<(f32, u32)> = units
+                .iter()
+                .enumerate()
+                .map(|(id, (position, ownership))| (planar_distance_sq(*position, center), id as u32, ownership))
+                .filter(|(distance_sq, _, ownership)| *distance_sq <= max_radius * max_radius && filter.matches(ownership))
+                .map(|(distance_sq, id, _)| (distance_sq, id))
+                .collect();
+            expected.sort_by(|a, b| a.0.total_cmp(&b.0));
+            expected.truncate(count);
+
+            let found: Vec<f32> = index
+                .nearest(center, count, max_radius, &filter)
+                .into_iter()
+                .map(|entity| planar_distance_sq(units[entity.index() as usize].0, center))
+                .collect();
+            // Compare distances, as units at the same distance may come in either order
+            let expected: Vec<f32> = expected.into_iter().map(|(distance_sq, _)| distance_sq).collect();
+            prop_assert_eq!(found, expected);
+        }
+
+        #[test]
+        fn radius_query_matches_a_full_scan(units in units(), x in -20.0f32..20.0, z in -20.0f32..20.0, radius in 0.0f32..15.0) {
+            let index = index_of(&units);
+            let center = Vec3::new(x, 0.0, z);
+            let expected: Vec<u32> = (0..units.len() as u32)
+                .filter(|id| planar_distance_sq(units[*id as usize].0, center) <= radius * radius)
+                .collect();
+            prop_assert_eq!(ids(index.query_radius(center, radius, &OwnershipFilter::Any)), expected);
+        }
+    }
+}
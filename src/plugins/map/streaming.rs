@@ -0,0 +1,205 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rts_camera::RtsCamera;
+use std::collections::HashSet;
+use crate::components::faction::{LocalPlayer, Ownership};
+use crate::components::unit::{GroundOffset, Unit};
+use crate::plugins::units::Corpse;
+use super::chunks::{CellStore, ChunkCoord};
+use super::grid::{GridCoord, GridCell, MapGrid};
+use super::loader::LoadMapCommand;
+
+/// How far around the camera and the local player's units chunks are kept loaded
+#[derive(Resource, Debug, Clone)]
+pub struct ChunkStreaming {
+    /// Chunks within this many chunks of an anchor get loaded
+    pub load_radius: i32,
+    /// Loaded chunks further than this from every anchor get unloaded
+    pub unload_radius: i32,
+    /// Most chunks spawned in one frame, spreading the cost of big jumps
+    pub max_loads_per_frame: usize,
+}
+
+impl Default for ChunkStreaming {
+    fn default() -> Self {
+        Self {
+            load_radius: 2,
+            unload_radius: 3,
+            max_loads_per_frame: 4,
+        }
+    }
+}
+
+/// Marker for entities standing in a chunk that isn't loaded. They are hidden and
+/// skipped by per-frame systems until their chunk loads again.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Dormant;
+
+/// Living units, of which the local player's and allies' ones anchor streaming
+type AnchorUnits<'w, 's> = Query<'w, 's, (&'static Ownership, &'static Transform), (With<Unit>, Without<Corpse>)>;
+
+/// System parameter finding what chunks are streamed around: the camera focus and the
+/// local player's and allies' units
+#[derive(SystemParam)]
+pub struct StreamingAnchors<'w, 's> {
+    local_player: Res<'w, LocalPlayer>,
+    cameras: Query<'w, 's, &'static RtsCamera>,
+    units: AnchorUnits<'w, 's>,
+}
+
+impl StreamingAnchors<'_, '_> {
+    /// Chunks every anchor currently stands in
+    pub fn chunks(&self, grid: &MapGrid) -> Vec<ChunkCoord> {
+        self.cameras
+            .iter()
+            .map(|camera| camera.target_focus.translation)
+            .chain(
+                self.units
+                    .iter()
+                    .filter(|(ownership, _)| self.local_player.is_friendly(ownership))
+                    .map(|(_, transform)| transform.translation),
+            )
+            .map(|position| ChunkCoord::of(grid.world_to_grid(position)))
+            .collect()
+    }
+}
+
+/// Hand a new map's cells to the store and summarize every chunk. Cell entities are
+/// spawned later, as chunks come into range.
+pub fn install_cells(commands: &mut Commands, grid: &mut MapGrid, cells: Vec<GridCell>) {
+    let store = CellStore::new(grid.width, grid.height, cells);
+    let (columns, rows) = grid.chunk_counts();
+    for y in 0..rows {
+        for x in 0..columns {
+            let chunk = ChunkCoord { x, y };
+            grid.set_chunk_summary(chunk, store.summarize(chunk));
+        }
+    }
+    commands.insert_resource(store);
+}
+
+/// Load chunks near the camera and the local player's units, and unload those far from all of them
+pub fn stream_chunks(
+    mut commands: Commands,
+    mut grid: ResMut<MapGrid>,
+    store: Option<Res<CellStore>>,
+    settings: Res<ChunkStreaming>,
+    anchors: StreamingAnchors,
+    mut load_commands: EventReader<LoadMapCommand>,
+) {
+    let Some(store) = store else {
+        return;
+    };
+    // The map is being replaced; cells spawned now would outlive it
+    if !load_commands.is_empty() {
+        load_commands.clear();
+        return;
+    }
+
+    let anchors = anchors.chunks(&grid);
+    let nearest_anchor = |chunk: ChunkCoord| {
+        anchors.iter().map(|anchor| anchor.chebyshev_distance(chunk)).min().unwrap_or(i32::MAX)
+    };
+
+    // Streaming doesn't replace the map, so keep it from looking like a new one
+    let grid = grid.bypass_change_detection();
+
+    let far: Vec<ChunkCoord> = grid
+        .loaded_chunks()
+        .filter(|chunk| nearest_anchor(**chunk) > settings.unload_radius)
+        .copied()
+        .collect();
+    for chunk in far {
+        for coord in chunk.cells() {
+            if let Some(entity) = grid.get_cell_entity(coord) {
+                commands.entity(*entity).despawn();
+            }
+        }
+        grid.unload_chunk(chunk);
+    }
+
+    let radius = settings.load_radius;
+    let mut wanted: Vec<ChunkCoord> = anchors
+        .iter()
+        .flat_map(|anchor| {
+            (-radius..=radius).flat_map(move |dy| {
+                (-radius..=radius).map(move |dx| ChunkCoord { x: anchor.x + dx, y: anchor.y + dy })
+            })
+        })
+        .filter(|chunk| grid.chunk_in_bounds(*chunk) && !grid.is_chunk_loaded(*chunk))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    // Closest chunks first, so what's in view appears before its surroundings
+    wanted.sort_by_key(|chunk| (nearest_anchor(*chunk), chunk.y, chunk.x));
+
+    for chunk in wanted.into_iter().take(settings.max_loads_per_frame) {
+        for coord in chunk.cells() {
+            if let Some(cell) = store.get(coord) {
+                let entity = commands.spawn((coord, cell.clone())).id();
+                grid.register_cell(coord, entity);
+            }
+        }
+        grid.mark_chunk_loaded(chunk);
+    }
+}
+
+/// Write edited cells back to the store and refresh the summaries of their chunks
+pub fn sync_cell_store(
+    mut grid: ResMut<MapGrid>,
+    store: Option<ResMut<CellStore>>,
+    changed: Query<(&GridCoord, &GridCell), Changed<GridCell>>,
+) {
+    let Some(mut store) = store else {
+        return;
+    };
+    if changed.is_empty() {
+        return;
+    }
+
+    for (coord, cell) in changed.iter() {
+        store.set(*coord, cell.clone());
+    }
+    let touched = changed.iter().map(|(coord, _)| *coord);
+    refresh_summaries(grid.bypass_change_detection(), &store, touched);
+}
+
+/// Re-summarize the chunks containing the given cells after the store changed
+pub(super) fn refresh_summaries(
+    grid: &mut MapGrid,
+    store: &CellStore,
+    cells: impl IntoIterator<Item = GridCoord>,
+) {
+    let mut touched = HashSet::new();
+    for coord in cells {
+        let chunk = ChunkCoord::of(coord);
+        // Open edges depend on the cells on both sides, so neighbors are refreshed too
+        touched.extend([(0, 0), (1, 0), (0, 1), (-1, 0), (0, -1)].map(|(dx, dy)| ChunkCoord {
+            x: chunk.x + dx,
+            y: chunk.y + dy,
+        }));
+    }
+    for chunk in touched {
+        if grid.chunk_in_bounds(chunk) {
+            grid.set_chunk_summary(chunk, store.summarize(chunk));
+        }
+    }
+}
+
+/// Put grounded entities in unloaded chunks to sleep, and wake them once their chunk loads
+pub fn update_dormant_entities(
+    mut commands: Commands,
+    grid: Res<MapGrid>,
+    mut entities: Query<(Entity, &Transform, &mut Visibility, Has<Dormant>), With<GroundOffset>>,
+) {
+    for (entity, transform, mut visibility, dormant) in entities.iter_mut() {
+        let loaded = grid.is_loaded(grid.world_to_grid(transform.translation));
+        if loaded && dormant {
+            commands.entity(entity).remove::<Dormant>();
+            *visibility = Visibility::Inherited;
+        } else if !loaded && !dormant {
+            commands.entity(entity).insert(Dormant);
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
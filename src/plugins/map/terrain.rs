@@ -0,0 +1,360 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde::de::{self, DeserializeSeed, VariantAccess, Visitor};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use super::grid::GridCell;
+
+/// Directory terrain definition files are read from; mods drop extra files in here
+pub const TERRAIN_DIRECTORY: &str = "assets/terrains";
+/// Extension used by terrain definition files
+pub const TERRAIN_EXTENSION: &str = "ron";
+/// Terrain new cells start out as
+pub const DEFAULT_TERRAIN: &str = "grass";
+/// Variants of the old built-in terrain enum, which maps saved before terrain
+/// definitions store as bare identifiers such as `terrain: Grass`
+const LEGACY_TERRAINS: [&str; 6] = ["Grass", "Dirt", "Stone", "Water", "Forest", "Mountain"];
+
+/// Identifier of a terrain definition, e.g. "grass"
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
+pub struct TerrainType(pub String);
+
+impl TerrainType {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+thread_local! {
+    /// Set while reading a map saved with the old terrain enum, whose terrains are bare
+    /// identifiers such as `terrain: Grass` rather than strings
+    static READING_LEGACY: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Read something saved with the old terrain enum, taking terrains as bare identifiers
+pub fn read_legacy_terrains<T>(read: impl FnOnce() -> T) -> T {
+    let previous = READING_LEGACY.replace(true);
+    let value = read();
+    READING_LEGACY.set(previous);
+    value
+}
+
+impl<'de> Deserialize<'de> for TerrainType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // RON reads enum variants and quoted strings through different calls, and can't
+        // be asked for either, so the reader says which form to expect
+        if READING_LEGACY.get() {
+            deserializer.deserialize_enum("TerrainType", &LEGACY_TERRAINS, TerrainTypeVisitor)
+        } else {
+            deserializer.deserialize_string(TerrainTypeVisitor)
+        }
+    }
+}
+
+/// Reads terrain ids, whether strings or the old enum variants, mapping the variant names
+/// to their ids
+struct TerrainTypeVisitor;
+
+impl<'de> Visitor<'de> for TerrainTypeVisitor {
+    type Value = TerrainType;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a terrain id or terrain variant")
+    }
+
+    fn visit_str<E: de::Error>(self, id: &str) -> Result<TerrainType, E> {
+        Ok(TerrainType::new(legacy_terrain_id(id).unwrap_or_else(|| id.to_string())))
+    }
+
+    fn visit_enum<A: de::EnumAccess<'de>>(self, data: A) -> Result<TerrainType, A::Error> {
+        let (terrain, variant) = data.variant_seed(self)?;
+        variant.unit_variant()?;
+        Ok(terrain)
+    }
+}
+
+/// Reads the name of an old enum variant as the terrain it stands for
+impl<'de> DeserializeSeed<'de> for TerrainTypeVisitor {
+    type Value = TerrainType;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<TerrainType, D::Error> {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+/// Id of an old terrain enum variant, e.g. "grass" for `Grass`
+fn legacy_terrain_id(name: &str) -> Option<String> {
+    LEGACY_TERRAINS.contains(&name).then(|| name.to_lowercase())
+}
+
+impl Default for TerrainType {
+    fn default() -> Self {
+        Self::new(DEFAULT_TERRAIN)
+    }
+}
+
+impl fmt::Display for TerrainType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// How a unit gets around, deciding which terrains it can cross
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum MovementClass {
+    #[default]
+    Ground,
+    Amphibious,
+    Naval,
+    Air,
+}
+
+fn default_color() -> (f32, f32, f32) {
+    (1.0, 0.0, 1.0)
+}
+
+fn default_move_cost() -> f32 {
+    1.0
+}
+
+/// Properties of one kind of terrain, as read from a terrain definition file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerrainDef {
+    pub id: TerrainType,
+    /// Name shown to players and in the editor
+    pub name: String,
+    /// Flat sRGB color used for the terrain mesh, minimap and overlays
+    #[serde(default = "default_color")]
+    pub color: (f32, f32, f32),
+    /// Texture asset for renderers that draw textured ground; `color` stands in for it elsewhere
+    #[serde(default)]
+    pub texture: Option<String>,
+    /// Movement classes that can cross this terrain
+    #[serde(default)]
+    pub walkable: HashSet<MovementClass>,
+    #[serde(default)]
+    pub buildable: bool,
+    /// Pathfinding cost of stepping onto the terrain, relative to open ground
+    #[serde(default = "default_move_cost")]
+    pub move_cost: f32,
+    /// Whether the terrain stops vision passing through it
+    #[serde(default)]
+    pub blocks_vision: bool,
+    /// Elevation cells get when painted with this terrain
+    #[serde(default)]
+    pub elevation: f32,
+}
+
+impl TerrainDef {
+    /// Stand-in for terrains missing from the registry: open, bright magenta ground
+    fn fallback(id: TerrainType) -> Self {
+        Self {
+            name: id.to_string(),
+            id,
+            color: default_color(),
+            texture: None,
+            walkable: HashSet::from([MovementClass::Ground, MovementClass::Amphibious, MovementClass::Air]),
+            buildable: true,
+            move_cost: default_move_cost(),
+            blocks_vision: false,
+            elevation: 0.0,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        let (r, g, b) = self.color;
+        Color::srgb(r, g, b)
+    }
+
+    /// Check if units of a movement class can cross this terrain
+    pub fn is_walkable_for(&self, class: MovementClass) -> bool {
+        self.walkable.contains(&class)
+    }
+
+    /// Reset a cell's properties to this terrain's defaults
+    pub fn apply_defaults(&self, cell: &mut GridCell) {
+        cell.walkable = self.is_walkable_for(MovementClass::Ground);
+        cell.buildable = self.buildable;
+        cell.elevation = self.elevation;
+    }
+}
+
+/// Resource holding every known terrain definition
+#[derive(Resource, Debug, Clone)]
+pub struct TerrainRegistry {
+    /// Definitions from the terrain directory, shared by all maps
+    base: Vec<TerrainDef>,
+    /// Definitions shipped with the current map, layered over the base ones
+    map: Vec<TerrainDef>,
+    definitions: HashMap<TerrainType, TerrainDef>,
+    /// Terrain ids in definition order, for editor shortcuts and listings
+    order: Vec<TerrainType>,
+    fallback: TerrainDef,
+}
+
+impl Default for TerrainRegistry {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl TerrainRegistry {
+    /// Registry of the given definitions; later definitions replace earlier ones with the same id
+    pub fn new(base: Vec<TerrainDef>) -> Self {
+        let mut registry = Self {
+            base,
+            map: Vec::new(),
+            definitions: HashMap::new(),
+            order: Vec::new(),
+            fallback: TerrainDef::fallback(TerrainType::default()),
+        };
+        registry.set_map_terrains(&[]);
+        registry
+    }
+
+    /// Read every definition file in the terrain directory, in file name order so mods
+    /// can override base terrains
+    pub fn load() -> Self {
+        let mut definitions = Vec::new();
+        match read_terrain_directory(Path::new(TERRAIN_DIRECTORY)) {
+            Ok(files) => {
+                for (path, text) in files {
+                    match ron::from_str::<Vec<TerrainDef>>(&text) {
+                        Ok(mut defs) => definitions.append(&mut defs),
+                        Err(error) => warn!("Failed to parse terrain file {}: {error}", path.display()),
+                    }
+                }
+            }
+            Err(error) => warn!("No terrain definitions loaded: {error}"),
+        }
+        info!("Loaded {} terrain definitions", definitions.len());
+        Self::new(definitions)
+    }
+
+    /// Replace the current map's own terrains, keeping the base definitions
+    pub fn set_map_terrains(&mut self, map: &[TerrainDef]) {
+        self.map = map.to_vec();
+        self.definitions.clear();
+        self.order.clear();
+        for def in self.base.iter().chain(&self.map) {
+            if self.definitions.insert(def.id.clone(), def.clone()).is_none() {
+                self.order.push(def.id.clone());
+            }
+        }
+    }
+
+    /// Terrains defined by the current map itself
+    pub fn map_terrains(&self) -> &[TerrainDef] {
+        &self.map
+    }
+
+    /// Definition of a terrain; unknown ids get open placeholder ground
+    pub fn get(&self, id: &TerrainType) -> &TerrainDef {
+        self.definitions.get(id).unwrap_or(&self.fallback)
+    }
+
+    /// All definitions in definition order
+    pub fn iter(&self) -> impl Iterator<Item = &TerrainDef> {
+        self.order.iter().map(|id| &self.definitions[id])
+    }
+
+    /// Cheapest move cost of any terrain, keeping path estimates from overshooting
+    pub fn min_move_cost(&self) -> f32 {
+        self.iter()
+            .map(|def| def.move_cost)
+            .chain([self.fallback.move_cost])
+            .fold(f32::INFINITY, f32::min)
+            .max(f32::EPSILON)
+    }
+}
+
+/// Contents of each definition file in a directory, sorted by path
+fn read_terrain_directory(directory: &Path) -> Result<Vec<(std::path::PathBuf, String)>, String> {
+    let entries = std::fs::read_dir(directory)
+        .map_err(|error| format!("failed to read {}: {error}", directory.display()))?;
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == TERRAIN_EXTENSION))
+        .collect();
+    paths.sort();
+
+    let mut files = Vec::new();
+    for path in paths {
+        match std::fs::read_to_string(&path) {
+            Ok(text) => files.push((path, text)),
+            Err(error) => warn!("Failed to read terrain file {}: {error}", path.display()),
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::map::{GridCell, MapFile};
+    use crate::plugins::triggers::Action;
+
+    fn parse_cell(text: &str) -> GridCell {
+        ron::from_str(text).unwrap()
+    }
+
+    /// One-cell map with the given cell and a message trigger
+    fn map_text(cell: &str, message: &str) -> String {
+        format!(
+            r#"(name: "test", width: 1, height: 1, cell_size: 1.0, cells: [{cell}], triggers: [
+                (name: "warn", event: MapStart, actions: [ShowMessage(text: {message:?}, seconds: 5.0)]),
+            ])"#
+        )
+    }
+
+    fn message(map: &MapFile) -> &str {
+        match &map.triggers[0].actions[0] {
+            Action::ShowMessage { text, .. } => text,
+            action => panic!("unexpected action {action:?}"),
+        }
+    }
+
+    #[test]
+    fn reads_terrain_ids() {
+        let cell = parse_cell(r#"(terrain: "forest", walkable: true, buildable: false, elevation: 0.0)"#);
+        assert_eq!(cell.terrain, TerrainType::new("forest"));
+    }
+
+    #[test]
+    fn reads_legacy_terrain_variants() {
+        for name in LEGACY_TERRAINS {
+            let text = format!("(terrain: {name}, walkable: true, buildable: true, elevation: 0.0)");
+            let cell: GridCell = read_legacy_terrains(|| ron::from_str(&text)).unwrap();
+            assert_eq!(cell.terrain, TerrainType::new(name.to_lowercase()));
+        }
+    }
+
+    #[test]
+    fn leaves_legacy_names_in_strings_alone() {
+        let warning = "Scorched terrain: Forest ahead";
+        let legacy = map_text("(terrain: Forest, walkable: true, buildable: false, elevation: 0.0)", warning);
+        let current = map_text(r#"(terrain: "lava", walkable: false, buildable: false, elevation: 0.0)"#, warning);
+
+        let map = MapFile::parse(&legacy).unwrap();
+        assert_eq!(map.cells[0].terrain, TerrainType::new("forest"));
+        assert_eq!(message(&map), warning);
+
+        let map = MapFile::parse(&current).unwrap();
+        assert_eq!(map.cells[0].terrain, TerrainType::new("lava"));
+        assert_eq!(message(&map), warning);
+    }
+
+    #[test]
+    fn round_trips_as_a_string() {
+        let text = ron::to_string(&TerrainType::new("water")).unwrap();
+        assert_eq!(text, r#""water""#);
+        assert_eq!(ron::from_str::<TerrainType>(&text).unwrap(), TerrainType::new("water"));
+    }
+}
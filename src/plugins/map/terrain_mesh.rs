@@ -0,0 +1,262 @@
+use bevy::prelude::*;
+use bevy::render::mesh::PrimitiveTopology;
+use bevy::render::render_asset::RenderAssetUsages;
+use super::grid::{GridCoord, GridCell, MapGrid};
+use super::terrain::TerrainRegistry;
+use super::topology::{GridTopology, HEX_NEIGHBORS};
+use super::TerrainMesh;
+
+/// Color of the exposed rock on cliff faces
+const CLIFF_COLOR: Color = Color::srgb(0.35, 0.3, 0.25);
+
+/// Corner offsets of a cell in grid space, counter-clockwise from the minimum corner
+const CORNERS: [(i32, i32); 4] = [(0, 0), (1, 0), (1, 1), (0, 1)];
+
+/// A cell edge: neighbor direction, corner indices along the edge, matching corners on the neighbor
+type Edge = ((i32, i32), [usize; 2], [usize; 2]);
+
+const EDGES: [Edge; 4] = [
+    ((1, 0), [1, 2], [0, 3]),
+    ((0, 1), [3, 2], [0, 1]),
+    ((-1, 0), [0, 3], [1, 2]),
+    ((0, -1), [0, 1], [3, 2]),
+];
+
+/// Vertex data of the terrain mesh while it is being built
+#[derive(Default)]
+struct TerrainMeshBuilder {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    colors: Vec<[f32; 4]>,
+    /// World size of the map, for UVs spanning the whole map
+    extent: Vec2,
+    /// UV given to every vertex instead, pinning faces to a single texel of map-wide textures
+    fixed_uv: Option<[f32; 2]>,
+}
+
+impl TerrainMeshBuilder {
+    /// Add a flat-shaded triangle, wound so its normal points along `facing`
+    fn triangle(&mut self, mut points: [Vec3; 3], facing: Vec3, color: Color) {
+        let mut normal = (points[1] - points[0]).cross(points[2] - points[0]);
+        if normal.length_squared() <= f32::EPSILON {
+            return;
+        }
+        if normal.dot(facing) < 0.0 {
+            points.swap(1, 2);
+            normal = -normal;
+        }
+
+        let normal = normal.normalize().to_array();
+        let color = color.to_linear().to_f32_array();
+        for point in points {
+            self.positions.push(point.to_array());
+            self.normals.push(normal);
+            self.uvs.push(self.fixed_uv.unwrap_or([point.x / self.extent.x, point.z / self.extent.y]));
+            self.colors.push(color);
+        }
+    }
+
+    fn quad(&mut self, corners: [Vec3; 4], facing: Vec3, color: Color) {
+        self.triangle([corners[0], corners[1], corners[2]], facing, color);
+        self.triangle([corners[0], corners[2], corners[3]], facing, color);
+    }
+
+    fn build(self) -> Mesh {
+        Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, self.colors)
+    }
+}
+
+/// Height of each corner of a cell.
+///
+/// Cells are flat, except ramps, whose corners touching a walkable neighbor one cliff
+/// level up are lifted to that neighbor's height so the ramp slopes into it.
+fn corner_heights<'a>(
+    cell_at: impl Fn(GridCoord) -> Option<&'a GridCell>,
+    coord: GridCoord,
+    cell: &GridCell,
+) -> [f32; 4] {
+    let base = cell.height();
+    if !cell.ramp {
+        return [base; 4];
+    }
+
+    CORNERS.map(|(corner_x, corner_y)| {
+        // The two orthogonal neighbors sharing this corner
+        let sides = [
+            GridCoord { x: coord.x + corner_x * 2 - 1, y: coord.y },
+            GridCoord { x: coord.x, y: coord.y + corner_y * 2 - 1 },
+        ];
+        sides
+            .into_iter()
+            .filter_map(&cell_at)
+            .filter(|side| side.walkable && !side.ramp && side.cliff_level == cell.cliff_level + 1)
+            .map(|side| side.height())
+            .fold(base, f32::max)
+    })
+}
+
+/// Build the terrain mesh from the grid's surface: a top face per cell colored by
+/// terrain, ramps sloping between levels and vertical cliff faces wherever neighboring
+/// cells meet at different heights. UVs map the grid onto a texture with one texel per
+/// cell (u along x, v along y) so overlays can be draped over it.
+pub fn build_terrain_mesh<'a>(
+    grid: &MapGrid,
+    terrains: &TerrainRegistry,
+    cell_at: impl Fn(GridCoord) -> Option<&'a GridCell>,
+) -> Mesh {
+    if grid.topology == GridTopology::Hex {
+        return build_hex_terrain_mesh(grid, terrains, cell_at);
+    }
+
+    let size = grid.cell_size;
+    let mut builder = TerrainMeshBuilder {
+        extent: Vec2::new(grid.width as f32 * size, grid.height as f32 * size).max(Vec2::splat(size)),
+        ..default()
+    };
+
+    let corner_position = |coord: GridCoord, corner: usize, height: f32| {
+        let (corner_x, corner_y) = CORNERS[corner];
+        Vec3::new((coord.x + corner_x) as f32 * size, height, (coord.y + corner_y) as f32 * size)
+    };
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let coord = GridCoord { x, y };
+            let Some(cell) = cell_at(coord) else {
+                continue;
+            };
+            let heights = grid.corner_heights(coord);
+            let top = [0, 1, 2, 3].map(|corner| corner_position(coord, corner, heights[corner]));
+            builder.quad(top, Vec3::Y, terrains.get(&cell.terrain).color());
+
+            for ((dx, dy), own, theirs) in EDGES {
+                let neighbor = GridCoord { x: x + dx, y: y + dy };
+                // Shared edges are built once, from the cell on their minimum side.
+                // Past the map border the surface reads as the ground plane.
+                if grid.in_bounds(neighbor) && (dx < 0 || dy < 0) {
+                    continue;
+                }
+                let neighbor_heights = grid.corner_heights(neighbor);
+
+                let near = own.map(|corner| heights[corner]);
+                let far = theirs.map(|corner| neighbor_heights[corner]);
+                if near == far {
+                    continue;
+                }
+
+                // The face looks out towards the lower side
+                let outward = Vec3::new(dx as f32, 0.0, dy as f32);
+                let facing = if near[0] + near[1] >= far[0] + far[1] { outward } else { -outward };
+                builder.quad(
+                    [
+                        corner_position(coord, own[0], near[0]),
+                        corner_position(coord, own[1], near[1]),
+                        corner_position(coord, own[1], far[1]),
+                        corner_position(coord, own[0], far[0]),
+                    ],
+                    facing,
+                    CLIFF_COLOR,
+                );
+            }
+        }
+    }
+
+    builder.build()
+}
+
+/// Hex terrain: a flat hexagon per cell, with walls down to any lower neighbor and to
+/// the ground plane along the map border. Every face of a cell samples that cell's texel.
+fn build_hex_terrain_mesh<'a>(
+    grid: &MapGrid,
+    terrains: &TerrainRegistry,
+    cell_at: impl Fn(GridCoord) -> Option<&'a GridCell>,
+) -> Mesh {
+    let mut builder = TerrainMeshBuilder::default();
+    // Pointy-top corners, where corner i and i + 1 bound the edge facing neighbor i
+    let radius = grid.cell_size / 3f32.sqrt();
+    let corners: [Vec3; 6] = std::array::from_fn(|i| {
+        let angle = (60.0 * i as f32 - 30.0).to_radians();
+        Vec3::new(angle.cos(), 0.0, angle.sin()) * radius
+    });
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let coord = GridCoord { x, y };
+            let Some(cell) = cell_at(coord) else {
+                continue;
+            };
+            builder.fixed_uv = Some([
+                (x as f32 + 0.5) / grid.width as f32,
+                (y as f32 + 0.5) / grid.height as f32,
+            ]);
+            let height = grid.cell_height(coord);
+            let center = grid.grid_to_world_at(coord, height);
+            let color = terrains.get(&cell.terrain).color();
+
+            for i in 1..5 {
+                builder.triangle([center + corners[0], center + corners[i], center + corners[i + 1]], Vec3::Y, color);
+            }
+
+            for (edge, (dq, dr)) in HEX_NEIGHBORS.into_iter().enumerate() {
+                let neighbor = GridCoord { x: x + dq, y: y + dr };
+                // Each wall is built by the higher cell; past the border lies the ground plane
+                let below = if grid.in_bounds(neighbor) { grid.cell_height(neighbor) } else { 0.0 };
+                if below >= height {
+                    continue;
+                }
+                let a = center + corners[edge];
+                let b = center + corners[(edge + 1) % 6];
+                let drop = Vec3::Y * (height - below);
+                let outward = (a + b) * 0.5 - center;
+                builder.quad([a, b, b - drop, a - drop], outward, CLIFF_COLOR);
+            }
+        }
+    }
+
+    builder.build()
+}
+
+/// Recompute the ground surface and rebuild the terrain mesh whenever cells or terrain
+/// definitions change, chunks stream in or out, or a new map is loaded
+pub fn update_terrain_surface(
+    mut grid: ResMut<MapGrid>,
+    terrains: Res<TerrainRegistry>,
+    cells: Query<&GridCell>,
+    changed: Query<(), Changed<GridCell>>,
+    mut unloaded: RemovedComponents<GridCell>,
+    terrain: Query<&Mesh3d, With<TerrainMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let any_unloaded = unloaded.read().count() > 0;
+    if !grid.is_changed() && !terrains.is_changed() && changed.is_empty() && !any_unloaded {
+        return;
+    }
+
+    let surface: Vec<[f32; 4]> = {
+        let cell_at = |coord| grid.get_cell_entity(coord).and_then(|entity| cells.get(*entity).ok());
+        (0..grid.height)
+            .flat_map(|y| (0..grid.width).map(move |x| GridCoord { x, y }))
+            .map(|coord| match cell_at(coord) {
+                // Ramps only slope on square grids
+                Some(cell) if grid.topology == GridTopology::Hex => [cell.height(); 4],
+                Some(cell) => corner_heights(cell_at, coord, cell),
+                None => [0.0; 4],
+            })
+            .collect()
+    };
+    // The surface follows the cells; it shouldn't look like a newly loaded map
+    grid.bypass_change_detection().set_surface(surface);
+
+    let cell_at = |coord| grid.get_cell_entity(coord).and_then(|entity| cells.get(*entity).ok());
+    let mesh = build_terrain_mesh(&grid, &terrains, cell_at);
+    for handle in terrain.iter() {
+        if let Some(existing) = meshes.get_mut(&handle.0) {
+            *existing = mesh.clone();
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use super::grid::GridCoord;
+
+/// Distance along Z between neighboring hex rows, relative to the cell size
+const HEX_ROW_SPACING: f32 = 0.866_025_4;
+/// Distance from a hex's center to its corners, relative to the cell size
+const HEX_CORNER_DISTANCE: f32 = 0.577_350_3;
+
+/// Axial neighbor offsets on a hex grid, counter-clockwise from +X
+pub const HEX_NEIGHBORS: [(i32, i32); 6] = [(1, 0), (0, 1), (-1, 1), (-1, 0), (0, -1), (1, -1)];
+
+/// How cells tile the map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum GridTopology {
+    /// Square cells with eight neighbors; `GridCoord` is column and row
+    #[default]
+    Square,
+    /// Pointy-top hexagons with six neighbors; `GridCoord` holds axial coordinates,
+    /// `x` as q and `y` as r. `cell_size` is the distance between neighboring centers.
+    Hex,
+}
+
+impl GridTopology {
+    /// Ground-plane (XZ) position of a cell's center
+    pub fn cell_center(self, coord: GridCoord, cell_size: f32) -> Vec2 {
+        match self {
+            GridTopology::Square => Vec2::new(coord.x as f32 + 0.5, coord.y as f32 + 0.5) * cell_size,
+            GridTopology::Hex => Vec2::new(
+                coord.x as f32 + coord.y as f32 * 0.5 + 0.5,
+                coord.y as f32 * HEX_ROW_SPACING + 0.5,
+            ) * cell_size,
+        }
+    }
+
+    /// Ground-plane (XZ) corners of a cell, in order around it
+    pub fn cell_corners(self, coord: GridCoord, cell_size: f32) -> Vec<Vec2> {
+        match self {
+            GridTopology::Square => [(0, 0), (1, 0), (1, 1), (0, 1)]
+                .map(|(dx, dy)| Vec2::new((coord.x + dx) as f32, (coord.y + dy) as f32) * cell_size)
+                .to_vec(),
+            GridTopology::Hex => {
+                let center = self.cell_center(coord, cell_size);
+                (0..6)
+                    .map(|i| {
+                        let angle = (60.0 * i as f32 - 30.0).to_radians();
+                        center + Vec2::new(angle.cos(), angle.sin()) * HEX_CORNER_DISTANCE * cell_size
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Ground-plane (XZ) size of the area from the world origin that every cell of a
+    /// `width` by `height` map lies in
+    pub fn map_extent(self, width: i32, height: i32, cell_size: f32) -> Vec2 {
+        match self {
+            GridTopology::Square => Vec2::new(width as f32, height as f32) * cell_size,
+            // Each row is shifted half a cell along X, and the last row's corners reach
+            // past its centers
+            GridTopology::Hex => {
+                let rows = (height - 1).max(0) as f32;
+                Vec2::new(width as f32 + rows * 0.5, rows * HEX_ROW_SPACING + 0.5 + HEX_CORNER_DISTANCE) * cell_size
+            }
+        }
+    }
+
+    /// Cell containing a ground-plane (XZ) position
+    pub fn world_to_cell(self, position: Vec2, cell_size: f32) -> GridCoord {
+        let position = position / cell_size;
+        match self {
+            GridTopology::Square => GridCoord {
+                x: position.x.floor() as i32,
+                y: position.y.floor() as i32,
+            },
+            GridTopology::Hex => {
+                let r = (position.y - 0.5) / HEX_ROW_SPACING;
+                let q = position.x - 0.5 - r * 0.5;
+                hex_round(q, r)
+            }
+        }
+    }
+
+    /// Cells sharing an edge (or, on square grids, a corner) with a cell
+    pub fn neighbors(self, coord: GridCoord) -> Vec<GridCoord> {
+        match self {
+            GridTopology::Square => coord.neighbors8().collect(),
+            GridTopology::Hex => HEX_NEIGHBORS.iter().map(|(dq, dr)| coord.offset(*dq, *dr)).collect(),
+        }
+    }
+
+    /// Distance between two cells in cell widths: straight-line on square grids, the
+    /// number of steps on hex grids
+    pub fn distance(self, a: GridCoord, b: GridCoord) -> f32 {
+        match self {
+            GridTopology::Square => {
+                let dx = (a.x - b.x) as f32;
+                let dy = (a.y - b.y) as f32;
+                (dx * dx + dy * dy).sqrt()
+            }
+            GridTopology::Hex => {
+                let dq = a.x - b.x;
+                let dr = a.y - b.y;
+                ((dq.abs() + dr.abs() + (dq + dr).abs()) / 2) as f32
+            }
+        }
+    }
+
+    /// Cells crossed by a straight line between two cells, both ends included
+    pub fn line(self, from: GridCoord, to: GridCoord) -> Vec<GridCoord> {
+        match self {
+            GridTopology::Square => from.line_to(to),
+            GridTopology::Hex => {
+                let steps = self.distance(from, to) as i32;
+                if steps == 0 {
+                    return vec![from];
+                }
+                // Nudge off the exact edges between hexes so rounding is consistent
+                let start = Vec2::new(from.x as f32 + 1e-6, from.y as f32 + 1e-6);
+                let end = Vec2::new(to.x as f32 + 1e-6, to.y as f32 + 1e-6);
+                (0..=steps)
+                    .map(|step| {
+                        let point = start.lerp(end, step as f32 / steps as f32);
+                        hex_round(point.x, point.y)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Nearest hex to fractional axial coordinates
+fn hex_round(q: f32, r: f32) -> GridCoord {
+    let s = -q - r;
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+    if dq > dr && dq > ds {
+        rq = -rr - rs;
+    } else if dr > ds {
+        rr = -rq - rs;
+    }
+    GridCoord { x: rq as i32, y: rr as i32 }
+}
@@ -1,19 +1,63 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use crate::components::unit::{Unit, UnitType, UnitState, Statsheet};
+use crate::components::unit::{Unit, UnitType, UnitState, Statsheet, GroundOffset};
 use crate::components::faction::{Ownership, FactionId, TeamId, ControllerType};
+use crate::plugins::blight::BlightSource;
 use super::grid::{GridCoord, MapGrid};
+use super::regions::RegionRegistry;
+
+/// Cell of the example creep camp that spreads blight
+const CREEP_CAMP: GridCoord = GridCoord { x: 6, y: 24 };
+/// Height of a unit's origin above the ground
+const UNIT_GROUND_OFFSET: f32 = 0.4;
+/// Height of the target marker above the ground
+const TARGET_GROUND_OFFSET: f32 = 0.2;
 
 /// Component to mark visualized unit entities
 #[derive(Component)]
 pub struct UnitVisualization;
 
+/// System parameter with the asset stores unit visuals are added to
+#[derive(SystemParam)]
+pub struct UnitAssets<'w> {
+    pub meshes: ResMut<'w, Assets<Mesh>>,
+    pub materials: ResMut<'w, Assets<StandardMaterial>>,
+}
+
 /// Spawn a simple test unit at a specified grid coordinate
 pub fn spawn_test_unit(
     commands: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
+    assets: &mut UnitAssets,
     grid: &Res<MapGrid>,
     coord: GridCoord,
+) -> Entity {
+    // Create unit ownership
+    let ownership = Ownership {
+        faction: FactionId::Player(1),
+        team: TeamId::Team(1),
+        controller_type: ControllerType::Human,
+    };
+    
+    spawn_unit(
+        commands,
+        assets,
+        grid,
+        coord,
+        "Test Warrior",
+        UnitType::Melee,
+        ownership,
+    )
+}
+
+/// Spawn a unit with a capsule visual in its faction's color
+pub fn spawn_unit(
+    commands: &mut Commands,
+    assets: &mut UnitAssets,
+    grid: &MapGrid,
+    coord: GridCoord,
+    name: &str,
+    unit_type: UnitType,
+    ownership: Ownership,
 ) -> Entity {
     // Create unit stats
     let mut stats = Statsheet::default();
@@ -23,35 +67,28 @@ pub fn spawn_test_unit(
     stats.calculate_derived_stats();
     stats.initialize();
     
-    // Create unit ownership
-    let ownership = Ownership {
-        faction: FactionId::Player(1),
-        team: TeamId::Team(1),
-        controller_type: ControllerType::Human,
-    };
-    
     // Create a simple mesh for the unit
-    let mesh = meshes.add(Mesh::from(Capsule3d {
+    let mesh = assets.meshes.add(Mesh::from(Capsule3d {
         radius: 0.3,
         half_length: 0.8,
         ..default()
     }));
     
-    // Create a blue material for the unit
-    let material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.1, 0.2, 0.8),
+    // Create a material in the owner's color
+    let material = assets.materials.add(StandardMaterial {
+        base_color: ownership.faction.color(),
         ..default()
     });
     
-    // Get the world position from grid coordinates
-    let position = grid.grid_to_world(coord, 0.4); // Slight elevation
+    // Get the world position from grid coordinates, standing on the ground
+    let position = grid.grid_to_world(coord) + Vec3::Y * UNIT_GROUND_OFFSET;
     
     // Spawn the unit entity
     commands.spawn((
         // Core unit components
         Unit {
-            name: "Test Warrior".to_string(),
-            unit_type: UnitType::Melee,
+            name: name.to_string(),
+            unit_type,
             state: UnitState::Idle,
             target: None,
         },
@@ -62,6 +99,7 @@ pub fn spawn_test_unit(
         Mesh3d(mesh),
         MeshMaterial3d(material),
         Transform::from_translation(position),
+        GroundOffset(UNIT_GROUND_OFFSET),
         UnitVisualization,
     )).id()
 }
@@ -69,55 +107,65 @@ pub fn spawn_test_unit(
 /// System that spawns some test units on the map
 pub fn spawn_example_units(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut assets: UnitAssets,
     grid: Res<MapGrid>,
+    regions: Res<RegionRegistry>,
     query: Query<&UnitVisualization>,
 ) {
     // Only spawn units if none exist yet
     if query.is_empty() {
-        // Spawn several units in different locations
-        spawn_test_unit(
-            &mut commands,
-            &mut meshes,
-            &mut materials,
-            &grid, 
-            GridCoord { x: 10, y: 10 }
-        );
+        // Spawn a unit on every cell of the spawn region
+        let spawn_cells = regions
+            .get("player_spawn")
+            .map(|region| region.cells())
+            .unwrap_or_default();
+        for coord in spawn_cells {
+            spawn_test_unit(
+                &mut commands,
+                &mut assets,
+                &grid, 
+                coord
+            );
+        }
         
-        spawn_test_unit(
+        // Spawn a creep camp that spreads blight around it
+        let camp = spawn_unit(
             &mut commands,
-            &mut meshes,
-            &mut materials,
-            &grid, 
-            GridCoord { x: 12, y: 10 }
+            &mut assets,
+            &grid,
+            CREEP_CAMP,
+            "Creep Camp",
+            UnitType::Building,
+            Ownership {
+                faction: FactionId::Creep,
+                team: TeamId::Neutral,
+                controller_type: ControllerType::AI,
+            },
         );
+        commands.entity(camp).insert(BlightSource::default());
         
-        spawn_test_unit(
-            &mut commands,
-            &mut meshes,
-            &mut materials,
-            &grid, 
-            GridCoord { x: 10, y: 12 }
-        );
+        // Spawn a "target" visualization at the center of the target region
+        let Some(target) = regions.get("target").and_then(|region| region.center()) else {
+            return;
+        };
         
-        // Spawn a "target" visualization at the center
-        let target_mesh = meshes.add(Mesh::from(Sphere {
+        let target_mesh = assets.meshes.add(Mesh::from(Sphere {
             radius: 0.2,
             ..default()
         }));
         
-        let target_material = materials.add(StandardMaterial {
+        let target_material = assets.materials.add(StandardMaterial {
             base_color: Color::srgb(0.9, 0.2, 0.2),
             ..default()
         });
         
-        let center_pos = grid.grid_to_world(GridCoord { x: 15, y: 15 }, 0.2);
+        let center_pos = grid.grid_to_world(target) + Vec3::Y * TARGET_GROUND_OFFSET;
         
         commands.spawn((
                 Mesh3d(target_mesh),
                 MeshMaterial3d(target_material),
                 Transform::from_translation(center_pos),
+                GroundOffset(TARGET_GROUND_OFFSET),
             Name::new("Target"),
         ));
     }
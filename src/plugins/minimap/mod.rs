@@ -0,0 +1,271 @@
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::ui::RelativeCursorPosition;
+use bevy_rts_camera::RtsCamera;
+use crate::components::unit::Unit;
+use crate::components::faction::Ownership;
+use crate::plugins::fog::{CellVisibility, FogQuery, FogUpdate};
+use crate::plugins::map::{color_bytes, GridCoord, GridCells, MapGrid};
+use crate::plugins::units::{Order, SelectedUnits};
+
+/// Minimap pixels per cell width
+const PIXELS_PER_CELL: f32 = 4.0;
+/// On-screen size of the minimap in logical pixels
+const MINIMAP_SIZE: f32 = 200.0;
+/// Brightness of explored cells that are not currently visible
+const EXPLORED_SHADE: f32 = 0.5;
+
+/// Marker component for the minimap UI node
+#[derive(Component)]
+pub struct Minimap;
+
+/// Texture the minimap is drawn into
+#[derive(Resource)]
+pub struct MinimapImage {
+    pub image: Handle<Image>,
+    width: i32,
+    height: i32,
+    /// Scale from world XZ to minimap pixels
+    pixels_per_unit: f32,
+    /// Index of the cell under each pixel, row-major; `None` off the map
+    pixel_cells: Vec<Option<usize>>,
+}
+
+/// Minimap plugin
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Update, (
+                setup_minimap,
+                draw_minimap,
+                handle_minimap_input,
+            ).chain().after(FogUpdate));
+    }
+}
+
+/// Create the minimap texture and UI node whenever a new map grid is installed
+fn setup_minimap(
+    mut commands: Commands,
+    cells: GridCells,
+    mut images: ResMut<Assets<Image>>,
+    minimaps: Query<Entity, With<Minimap>>,
+) {
+    if !cells.grid.is_changed() {
+        return;
+    }
+
+    for entity in minimaps.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    // Pixels cover the map's world area, whatever shape its cells have
+    let grid = &cells.grid;
+    let pixels_per_unit = PIXELS_PER_CELL / grid.cell_size;
+    let size = (grid.world_extent() * pixels_per_unit).ceil();
+    let (width, height) = (size.x as i32, size.y as i32);
+    let pixel_cells = (0..width * height)
+        .map(|index| {
+            let pixel = Vec2::new((index % width) as f32 + 0.5, (index / width) as f32 + 0.5);
+            let coord = grid.world_to_grid(Vec3::new(pixel.x / pixels_per_unit, 0.0, pixel.y / pixels_per_unit));
+            MapGrid::cell_index(grid.width, grid.height, coord)
+        })
+        .collect();
+
+    let image = images.add(Image::new_fill(
+        Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    ));
+
+    // Keep the map's aspect ratio inside the minimap square
+    let aspect = width as f32 / height as f32;
+    let (node_width, node_height) = if aspect >= 1.0 {
+        (MINIMAP_SIZE, MINIMAP_SIZE / aspect)
+    } else {
+        (MINIMAP_SIZE * aspect, MINIMAP_SIZE)
+    };
+
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.0),
+            bottom: Val::Px(10.0),
+            width: Val::Px(node_width),
+            height: Val::Px(node_height),
+            border: UiRect::all(Val::Px(2.0)),
+            ..default()
+        },
+        BorderColor(Color::srgb(0.2, 0.2, 0.2)),
+        ImageNode::new(image.clone()),
+        RelativeCursorPosition::default(),
+        Minimap,
+        Name::new("Minimap"),
+    ));
+
+    commands.insert_resource(MinimapImage { image, width, height, pixels_per_unit, pixel_cells });
+}
+
+/// Redraw terrain, fog, unit dots and the camera view outline
+fn draw_minimap(
+    cells: GridCells,
+    fog: FogQuery,
+    minimap: Option<Res<MinimapImage>>,
+    mut images: ResMut<Assets<Image>>,
+    units: Query<(Entity, &Ownership, &Transform), With<Unit>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<RtsCamera>>,
+) {
+    let Some(minimap) = minimap else {
+        return;
+    };
+    let Some(image) = images.get_mut(&minimap.image) else {
+        return;
+    };
+    let Some(data) = image.data.as_mut() else {
+        return;
+    };
+    let mut canvas = Canvas { data, width: minimap.width, height: minimap.height };
+    let grid = &cells.grid;
+
+    // Terrain shaded by the local player's fog state, painted onto the pixels of each cell
+    let cell_colors: Vec<[u8; 4]> = (0..grid.width * grid.height)
+        .map(|index| {
+            let coord = GridCoord { x: index % grid.width, y: index / grid.width };
+            match fog.local_cell_state(coord) {
+                CellVisibility::Unexplored => [0, 0, 0, 255],
+                state => {
+                    let terrain = cells.get(coord).map(|cell| cells.terrains.get(&cell.terrain).color()).unwrap_or(Color::BLACK);
+                    let shade = if state == CellVisibility::Visible { 1.0 } else { EXPLORED_SHADE };
+                    color_bytes(terrain, shade)
+                }
+            }
+        })
+        .collect();
+    for (pixel, cell) in minimap.pixel_cells.iter().enumerate() {
+        let color = cell.and_then(|index| cell_colors.get(index)).copied().unwrap_or([0, 0, 0, 255]);
+        canvas.data[pixel * 4..pixel * 4 + 4].copy_from_slice(&color);
+    }
+
+    // Friendly units always, enemies only while in vision
+    let pixels_per_unit = minimap.pixels_per_unit;
+    for (entity, ownership, transform) in units.iter() {
+        if !fog.is_visible_to_local_player(entity) {
+            continue;
+        }
+        let px = (transform.translation.x * pixels_per_unit) as i32;
+        let py = (transform.translation.z * pixels_per_unit) as i32;
+        canvas.fill_rect(px - 1, py - 1, 3, 3, color_bytes(ownership.faction.color(), 1.0));
+    }
+
+    // Outline of the ground area covered by the camera
+    for (camera, camera_transform) in cameras.iter() {
+        let Some(viewport) = camera.logical_viewport_size() else {
+            continue;
+        };
+        let corners = [
+            Vec2::ZERO,
+            Vec2::new(viewport.x, 0.0),
+            viewport,
+            Vec2::new(0.0, viewport.y),
+        ];
+        let points: Vec<GridCoord> = corners
+            .iter()
+            .filter_map(|corner| ground_point(camera, camera_transform, *corner))
+            .map(|point| GridCoord {
+                x: (point.x * pixels_per_unit) as i32,
+                y: (point.z * pixels_per_unit) as i32,
+            })
+            .collect();
+        if points.len() < corners.len() {
+            continue;
+        }
+        for i in 0..points.len() {
+            canvas.line(points[i], points[(i + 1) % points.len()], [255, 255, 255, 255]);
+        }
+    }
+}
+
+/// Left click or drag pans the camera, right click sends selected units there
+fn handle_minimap_input(
+    mouse: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    cells: GridCells,
+    minimaps: Query<&RelativeCursorPosition, With<Minimap>>,
+    mut cameras: Query<&mut RtsCamera>,
+    mut selected: SelectedUnits,
+) {
+    let Some(cursor) = minimaps.iter().find(|cursor| cursor.mouse_over()) else {
+        return;
+    };
+    let Some(normalized) = cursor.normalized else {
+        return;
+    };
+    let grid = &cells.grid;
+    let extent = grid.world_extent();
+    let target = Vec3::new(normalized.x * extent.x, 0.0, normalized.y * extent.y);
+
+    if mouse.pressed(MouseButton::Left) {
+        for mut camera in cameras.iter_mut() {
+            camera.target_focus.translation.x = target.x;
+            camera.target_focus.translation.z = target.z;
+        }
+    }
+
+    if mouse.just_pressed(MouseButton::Right) {
+        let to = grid.world_to_grid(target);
+        // Shift queues the move after the units' current orders
+        let queued = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        selected.order(Order::Move(to), queued);
+    }
+}
+
+/// Where a viewport position lands on the ground plane
+fn ground_point(camera: &Camera, camera_transform: &GlobalTransform, position: Vec2) -> Option<Vec3> {
+    let ray = camera.viewport_to_world(camera_transform, position).ok()?;
+    let distance = ray.intersect_plane(Vec3::ZERO, InfinitePlane3d::new(Vec3::Y))?;
+    Some(ray.get_point(distance))
+}
+
+/// Pixel drawing helpers over raw RGBA image data
+struct Canvas<'a> {
+    data: &'a mut Vec<u8>,
+    width: i32,
+    height: i32,
+}
+
+impl Canvas<'_> {
+    fn put(&mut self, x: i32, y: i32, color: [u8; 4]) {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
+            return;
+        }
+        let index = ((y * self.width + x) * 4) as usize;
+        self.data[index..index + 4].copy_from_slice(&color);
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: [u8; 4]) {
+        for py in y..y + height {
+            for px in x..x + width {
+                self.put(px, py, color);
+            }
+        }
+    }
+
+    fn line(&mut self, from: GridCoord, to: GridCoord, color: [u8; 4]) {
+        // Clamp far-off points so huge horizon rays don't produce endless lines
+        let clamp = |point: GridCoord| GridCoord {
+            x: point.x.clamp(-1, self.width),
+            y: point.y.clamp(-1, self.height),
+        };
+        for pixel in clamp(from).line_to(clamp(to)) {
+            self.put(pixel.x, pixel.y, color);
+        }
+    }
+}
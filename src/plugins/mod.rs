@@ -1,2 +1,10 @@
+pub mod blight;
 pub mod camera;
+pub mod debug;
+pub mod editor;
+pub mod fog;
 pub mod map;
+pub mod minimap;
+pub mod scripting;
+pub mod triggers;
+pub mod units;
\ No newline at end of file
@@ -0,0 +1,328 @@
+use bevy::prelude::*;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, FLOAT, INT};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crate::components::unit::{Statsheet, UnitType};
+use crate::components::faction::FactionId;
+use crate::plugins::map::{GridCell, GridCoord, Region, TerrainType, MAX_CLIFF_LEVEL};
+use super::MAX_QUEUED_COMMANDS;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Unit data readable from scripts
+#[derive(Debug, Clone)]
+pub struct UnitSnapshot {
+    pub name: String,
+    pub unit_type: UnitType,
+    pub faction: FactionId,
+    pub coord: GridCoord,
+    pub stats: Statsheet,
+    pub alive: bool,
+}
+
+/// World changes requested by a script, applied after it returns
+#[derive(Debug, Clone)]
+pub enum ScriptCommand {
+    SpawnUnit { name: String, unit_type: UnitType, faction: FactionId, coord: GridCoord },
+    SetStat { entity: Entity, stat: String, value: f32 },
+    MoveUnit { entity: Entity, to: GridCoord },
+    /// Change terrain type, using its default properties
+    SetTerrain { coord: GridCoord, terrain: TerrainType },
+    /// Set a cell to an exact state
+    SetCell { coord: GridCoord, cell: GridCell },
+    ShowMessage { text: String, seconds: f32 },
+}
+
+/// World snapshot and command queue shared between the engine and the ECS
+#[derive(Debug, Default)]
+pub struct ScriptState {
+    pub units: HashMap<Entity, UnitSnapshot>,
+    pub cells: HashMap<GridCoord, GridCell>,
+    pub regions: Vec<Region>,
+    /// Ids of every known terrain
+    pub terrains: Vec<TerrainType>,
+    pub commands: Vec<ScriptCommand>,
+}
+
+impl ScriptState {
+    fn unit_entity(&self, id: INT) -> ScriptResult<Entity> {
+        entity_from_id(id)
+            .filter(|entity| self.units.contains_key(entity))
+            .ok_or_else(|| format!("unknown unit {id}").into())
+    }
+
+    fn unit(&self, id: INT) -> ScriptResult<&UnitSnapshot> {
+        Ok(&self.units[&self.unit_entity(id)?])
+    }
+
+    /// Queue a world change; a script queuing too many in one frame fails instead
+    fn queue(&mut self, command: ScriptCommand) -> ScriptResult<()> {
+        if self.commands.len() >= MAX_QUEUED_COMMANDS {
+            return Err(format!("more than {MAX_QUEUED_COMMANDS} world changes queued this frame").into());
+        }
+        self.commands.push(command);
+        Ok(())
+    }
+
+    /// Living units matching a filter
+    fn living_units(&self, filter: impl Fn(&UnitSnapshot) -> bool) -> Array {
+        id_array(
+            self.units
+                .iter()
+                .filter(|(_, unit)| unit.alive && filter(unit))
+                .map(|(entity, _)| *entity)
+                .collect(),
+        )
+    }
+
+    fn cell(&self, x: INT, y: INT) -> ScriptResult<&GridCell> {
+        self.cells
+            .get(&coord(x, y))
+            .ok_or_else(|| format!("cell ({x}, {y}) is outside the map").into())
+    }
+
+    /// Queue a full cell update and keep the snapshot in step with it
+    fn edit_cell(&mut self, x: INT, y: INT, edit: impl FnOnce(&mut GridCell)) -> ScriptResult<()> {
+        let mut cell = self.cell(x, y)?.clone();
+        edit(&mut cell);
+        self.queue(ScriptCommand::SetCell { coord: coord(x, y), cell: cell.clone() })?;
+        self.cells.insert(coord(x, y), cell);
+        Ok(())
+    }
+}
+
+/// Script-side id of an entity
+pub fn entity_id(entity: Entity) -> INT {
+    entity.to_bits() as INT
+}
+
+fn entity_from_id(id: INT) -> Option<Entity> {
+    Entity::try_from_bits(id as u64).ok()
+}
+
+fn coord(x: INT, y: INT) -> GridCoord {
+    GridCoord { x: x as i32, y: y as i32 }
+}
+
+/// Scripts refer to players by number; anything below 1 is hostile creeps
+fn faction_from_player(player: INT) -> FactionId {
+    if player >= 1 {
+        FactionId::Player(player as u32)
+    } else {
+        FactionId::Creep
+    }
+}
+
+fn player_number(faction: &FactionId) -> INT {
+    match faction {
+        FactionId::Player(player) => *player as INT,
+        _ => 0,
+    }
+}
+
+fn unit_type_name(unit_type: &UnitType) -> &'static str {
+    match unit_type {
+        UnitType::Hero => "hero",
+        UnitType::Building => "building",
+        UnitType::Melee => "melee",
+        UnitType::Ranged => "ranged",
+        UnitType::Caster => "caster",
+        UnitType::Worker => "worker",
+    }
+}
+
+fn unit_type_from_name(name: &str) -> ScriptResult<UnitType> {
+    match name.to_ascii_lowercase().as_str() {
+        "hero" => Ok(UnitType::Hero),
+        "building" => Ok(UnitType::Building),
+        "melee" => Ok(UnitType::Melee),
+        "ranged" => Ok(UnitType::Ranged),
+        "caster" => Ok(UnitType::Caster),
+        "worker" => Ok(UnitType::Worker),
+        _ => Err(format!("unknown unit type '{name}'").into()),
+    }
+}
+
+fn id_array(mut entities: Vec<Entity>) -> Array {
+    entities.sort();
+    entities.into_iter().map(|entity| Dynamic::from(entity_id(entity))).collect()
+}
+
+/// Register the game API on a script engine
+///
+/// Reads come from the snapshot taken before the script runs. Writes are queued as
+/// commands and applied once it returns; stat and cell writes also update the snapshot
+/// so a script sees its own changes.
+pub fn register_api(engine: &mut Engine, state: &Arc<Mutex<ScriptState>>) {
+    // Units
+    let shared = state.clone();
+    engine.register_fn("units", move || -> Array {
+        shared.lock().unwrap().living_units(|_| true)
+    });
+    let shared = state.clone();
+    engine.register_fn("units_of", move |player: INT| -> Array {
+        let faction = faction_from_player(player);
+        shared.lock().unwrap().living_units(|unit| unit.faction == faction)
+    });
+    let shared = state.clone();
+    engine.register_fn("units_in_region", move |name: &str| -> ScriptResult<Array> {
+        let state = shared.lock().unwrap();
+        let region = state
+            .regions
+            .iter()
+            .find(|region| region.name == name)
+            .ok_or_else(|| format!("unknown region '{name}'"))?;
+        Ok(state.living_units(|unit| region.contains(unit.coord)))
+    });
+    let shared = state.clone();
+    engine.register_fn("unit_exists", move |id: INT| -> bool {
+        shared.lock().unwrap().unit(id).is_ok()
+    });
+    let shared = state.clone();
+    engine.register_fn("unit_alive", move |id: INT| -> ScriptResult<bool> {
+        Ok(shared.lock().unwrap().unit(id)?.alive)
+    });
+    let shared = state.clone();
+    engine.register_fn("unit_name", move |id: INT| -> ScriptResult<String> {
+        Ok(shared.lock().unwrap().unit(id)?.name.clone())
+    });
+    let shared = state.clone();
+    engine.register_fn("unit_type", move |id: INT| -> ScriptResult<String> {
+        Ok(unit_type_name(&shared.lock().unwrap().unit(id)?.unit_type).to_string())
+    });
+    let shared = state.clone();
+    engine.register_fn("unit_owner", move |id: INT| -> ScriptResult<INT> {
+        Ok(player_number(&shared.lock().unwrap().unit(id)?.faction))
+    });
+    let shared = state.clone();
+    engine.register_fn("unit_cell", move |id: INT| -> ScriptResult<Map> {
+        let cell = shared.lock().unwrap().unit(id)?.coord;
+        let mut map = Map::new();
+        map.insert("x".into(), Dynamic::from(cell.x as INT));
+        map.insert("y".into(), Dynamic::from(cell.y as INT));
+        Ok(map)
+    });
+    let shared = state.clone();
+    engine.register_fn(
+        "spawn_unit",
+        move |name: &str, unit_type: &str, player: INT, x: INT, y: INT| -> ScriptResult<()> {
+            let mut state = shared.lock().unwrap();
+            state.cell(x, y)?;
+            state.queue(ScriptCommand::SpawnUnit {
+                name: name.to_string(),
+                unit_type: unit_type_from_name(unit_type)?,
+                faction: faction_from_player(player),
+                coord: coord(x, y),
+            })
+        },
+    );
+    let shared = state.clone();
+    engine.register_fn("order_move", move |id: INT, x: INT, y: INT| -> ScriptResult<()> {
+        let mut state = shared.lock().unwrap();
+        let entity = state.unit_entity(id)?;
+        state.cell(x, y)?;
+        state.queue(ScriptCommand::MoveUnit { entity, to: coord(x, y) })
+    });
+
+    // Stats
+    let shared = state.clone();
+    engine.register_fn("get_stat", move |id: INT, stat: &str| -> ScriptResult<FLOAT> {
+        let state = shared.lock().unwrap();
+        let value = state
+            .unit(id)?
+            .stats
+            .get_stat(stat)
+            .ok_or_else(|| format!("unknown stat '{stat}'"))?;
+        Ok(value as FLOAT)
+    });
+    let set_stat = {
+        let shared = state.clone();
+        move |id: INT, stat: &str, value: FLOAT| -> ScriptResult<()> {
+            let mut state = shared.lock().unwrap();
+            let entity = state.unit_entity(id)?;
+            let unit = state.units.get_mut(&entity).unwrap();
+            if !unit.stats.set_stat(stat, value as f32) {
+                return Err(format!("unknown stat '{stat}'").into());
+            }
+            state.queue(ScriptCommand::SetStat {
+                entity,
+                stat: stat.to_string(),
+                value: value as f32,
+            })
+        }
+    };
+    engine.register_fn("set_stat", set_stat.clone());
+    engine.register_fn("set_stat", move |id: INT, stat: &str, value: INT| {
+        set_stat(id, stat, value as FLOAT)
+    });
+
+    // Terrain
+    let shared = state.clone();
+    engine.register_fn("terrain_at", move |x: INT, y: INT| -> ScriptResult<String> {
+        Ok(shared.lock().unwrap().cell(x, y)?.terrain.to_string())
+    });
+    let shared = state.clone();
+    engine.register_fn("elevation_at", move |x: INT, y: INT| -> ScriptResult<FLOAT> {
+        Ok(shared.lock().unwrap().cell(x, y)?.elevation as FLOAT)
+    });
+    let shared = state.clone();
+    engine.register_fn("is_walkable", move |x: INT, y: INT| -> ScriptResult<bool> {
+        Ok(shared.lock().unwrap().cell(x, y)?.walkable)
+    });
+    let shared = state.clone();
+    engine.register_fn("is_buildable", move |x: INT, y: INT| -> ScriptResult<bool> {
+        Ok(shared.lock().unwrap().cell(x, y)?.buildable)
+    });
+    let shared = state.clone();
+    engine.register_fn("set_terrain", move |x: INT, y: INT, name: &str| -> ScriptResult<()> {
+        let mut state = shared.lock().unwrap();
+        let terrain = state
+            .terrains
+            .iter()
+            .find(|terrain| terrain.as_str().eq_ignore_ascii_case(name))
+            .cloned()
+            .ok_or_else(|| format!("unknown terrain '{name}'"))?;
+        state.cell(x, y)?;
+        state.queue(ScriptCommand::SetTerrain { coord: coord(x, y), terrain })
+    });
+    let set_elevation = {
+        let shared = state.clone();
+        move |x: INT, y: INT, elevation: FLOAT| -> ScriptResult<()> {
+            shared.lock().unwrap().edit_cell(x, y, |cell| cell.elevation = elevation as f32)
+        }
+    };
+    engine.register_fn("set_elevation", set_elevation.clone());
+    engine.register_fn("set_elevation", move |x: INT, y: INT, elevation: INT| {
+        set_elevation(x, y, elevation as FLOAT)
+    });
+    let shared = state.clone();
+    engine.register_fn("cliff_level_at", move |x: INT, y: INT| -> ScriptResult<INT> {
+        Ok(shared.lock().unwrap().cell(x, y)?.cliff_level as INT)
+    });
+    let shared = state.clone();
+    engine.register_fn("set_cliff_level", move |x: INT, y: INT, level: INT| -> ScriptResult<()> {
+        let level = level.clamp(0, MAX_CLIFF_LEVEL as INT) as i32;
+        shared.lock().unwrap().edit_cell(x, y, |cell| cell.cliff_level = level)
+    });
+    let shared = state.clone();
+    engine.register_fn("set_ramp", move |x: INT, y: INT, ramp: bool| -> ScriptResult<()> {
+        shared.lock().unwrap().edit_cell(x, y, |cell| cell.ramp = ramp)
+    });
+    let shared = state.clone();
+    engine.register_fn("set_walkable", move |x: INT, y: INT, walkable: bool| -> ScriptResult<()> {
+        shared.lock().unwrap().edit_cell(x, y, |cell| cell.walkable = walkable)
+    });
+    let shared = state.clone();
+    engine.register_fn("set_buildable", move |x: INT, y: INT, buildable: bool| -> ScriptResult<()> {
+        shared.lock().unwrap().edit_cell(x, y, |cell| cell.buildable = buildable)
+    });
+
+    // Messages
+    let shared = state.clone();
+    engine.register_fn("show_message", move |text: &str, seconds: FLOAT| -> ScriptResult<()> {
+        shared.lock().unwrap().queue(ScriptCommand::ShowMessage {
+            text: text.to_string(),
+            seconds: seconds as f32,
+        })
+    });
+}
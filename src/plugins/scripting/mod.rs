@@ -0,0 +1,384 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{CallFnOptions, Dynamic, Engine, FuncArgs, Map, Scope, AST, FLOAT};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use crate::components::unit::{Unit, Statsheet};
+use crate::components::faction::{Ownership, FactionId, TeamId, ControllerType};
+use crate::plugins::map::{
+    spawn_unit, BuildingPlacedEvent, GridCell, GridCoord, LoadedMap, MapFile, MapGrid,
+    RegionRegistry, TerrainModifiedEvent, TerrainRegistry, UnitAssets, SCRIPT_EXTENSION,
+    TerrainUpdate, UnitEnteredRegion, UnitLeftRegion, UnitMoveEvent,
+};
+use crate::plugins::triggers::GameMessages;
+use crate::plugins::units::{Corpse, IssueOrderEvent, Order, UnitDiedEvent};
+
+mod api;
+
+use api::{entity_id, ScriptCommand, ScriptState, UnitSnapshot};
+
+/// Sandbox limits, so a runaway script cannot stall the game
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_STRING_SIZE: usize = 64 * 1024;
+const MAX_COLLECTION_SIZE: usize = 10_000;
+/// Most world changes scripts may queue in one frame
+const MAX_QUEUED_COMMANDS: usize = 1_000;
+
+/// Source text of a map script
+#[derive(Asset, TypePath)]
+pub struct MapScript {
+    text: String,
+}
+
+/// Asset loader for map scripts, so the asset server reports edits made to them on disk
+#[derive(Default)]
+struct MapScriptLoader;
+
+impl AssetLoader for MapScriptLoader {
+    type Asset = MapScript;
+    type Settings = ();
+    type Error = String;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<MapScript, String> {
+        let path = load_context.path().display();
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(|error| format!("failed to read {path}: {error}"))?;
+        let text = String::from_utf8(bytes).map_err(|error| format!("failed to read {path}: {error}"))?;
+        Ok(MapScript { text })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &[SCRIPT_EXTENSION]
+    }
+}
+
+/// A compiled map script
+struct LoadedScript {
+    path: PathBuf,
+    ast: AST,
+    /// Object map bound to `this` in handlers; survives hot reloads
+    globals: Dynamic,
+    /// Handlers the script defines and that have not failed
+    handlers: HashSet<String>,
+}
+
+impl LoadedScript {
+    /// Call a handler if the script defines it; a failing handler is disabled until the next reload
+    fn call(&mut self, engine: &Engine, handler: &str, args: impl FuncArgs) {
+        if !self.handlers.contains(handler) {
+            return;
+        }
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.globals);
+        let result = engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &self.ast, handler, args);
+        if let Err(error) = result {
+            error!("{} failed in {handler}: {error}", self.path.display());
+            self.handlers.remove(handler);
+        }
+    }
+}
+
+/// Resource holding the script engine and the current map's script
+///
+/// Scripts are Rhai files shipped next to the map (`assets/maps/<map>.rhai`). They can
+/// define any of these handlers, which are called when the matching game event happens:
+///
+/// - `on_map_start()` and `on_reload()`
+/// - `on_tick(seconds)`
+/// - `on_unit_moves(unit, x, y)`
+/// - `on_unit_enters_region(unit, region)` and `on_unit_leaves_region(unit, region)`
+/// - `on_building_placed(unit)`
+/// - `on_unit_dies(unit)`
+///
+/// Scripts have no file or module access, and a frame's handlers together may queue at
+/// most `MAX_QUEUED_COMMANDS` world changes. Handlers keep state in `this`.
+#[derive(Resource)]
+pub struct ScriptRuntime {
+    engine: Engine,
+    state: Arc<Mutex<ScriptState>>,
+    script: Option<LoadedScript>,
+    /// Source of the current map's script, if it has one
+    source: Option<Handle<MapScript>>,
+    /// Map the current script belongs to
+    map_name: String,
+}
+
+impl Default for ScriptRuntime {
+    fn default() -> Self {
+        let state = Arc::new(Mutex::new(ScriptState::default()));
+
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_string_size(MAX_STRING_SIZE)
+            .set_max_array_size(MAX_COLLECTION_SIZE)
+            .set_max_map_size(MAX_COLLECTION_SIZE)
+            .set_max_modules(0)
+            .set_module_resolver(DummyModuleResolver::new())
+            .disable_symbol("eval")
+            .on_print(|text| info!("[script] {text}"))
+            .on_debug(|text, _, position| debug!("[script] {position}: {text}"));
+        api::register_api(&mut engine, &state);
+
+        Self {
+            engine,
+            state,
+            script: None,
+            source: None,
+            map_name: String::new(),
+        }
+    }
+}
+
+impl ScriptRuntime {
+    /// Compile the script read from `path`, keeping `globals` from a previous version
+    fn compile(&mut self, path: PathBuf, text: &str, globals: Dynamic) -> Result<LoadedScript, String> {
+        let ast = self
+            .engine
+            .compile(text)
+            .map_err(|error| format!("failed to compile {}: {error}", path.display()))?;
+        let handlers = ast.iter_functions().map(|function| function.name.to_string()).collect();
+
+        Ok(LoadedScript { path, ast, globals, handlers })
+    }
+}
+
+/// Units as scripts see them
+type SnapshotUnits<'w, 's> = Query<
+    'w,
+    's,
+    (Entity, &'static Unit, &'static Ownership, &'static Statsheet, &'static Transform, Has<Corpse>),
+>;
+
+/// System parameter with everything script commands change
+#[derive(SystemParam)]
+struct ScriptEffects<'w, 's> {
+    commands: Commands<'w, 's>,
+    unit_assets: UnitAssets<'w>,
+    stats: Query<'w, 's, &'static mut Statsheet>,
+    messages: ResMut<'w, GameMessages>,
+    terrain_events: EventWriter<'w, TerrainModifiedEvent>,
+    order_events: EventWriter<'w, IssueOrderEvent>,
+}
+
+/// Map scripting plugin
+pub struct ScriptingPlugin;
+
+impl Plugin for ScriptingPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_asset::<MapScript>()
+            .init_asset_loader::<MapScriptLoader>()
+            .init_resource::<ScriptRuntime>()
+            .add_systems(Update, (
+                sync_script_state,
+                load_map_script,
+                dispatch_script_events,
+                apply_script_commands,
+            ).chain().before(TerrainUpdate));
+    }
+}
+
+/// Refresh the world snapshot scripts read from
+fn sync_script_state(
+    runtime: Res<ScriptRuntime>,
+    grid: Res<MapGrid>,
+    regions: Res<RegionRegistry>,
+    terrains: Res<TerrainRegistry>,
+    units: SnapshotUnits,
+    cells: Query<(&GridCoord, Ref<GridCell>)>,
+) {
+    let mut state = runtime.state.lock().unwrap();
+
+    state.units = units
+        .iter()
+        .map(|(entity, unit, ownership, stats, transform, dead)| {
+            let snapshot = UnitSnapshot {
+                name: unit.name.clone(),
+                unit_type: unit.unit_type.clone(),
+                faction: ownership.faction.clone(),
+                coord: grid.world_to_grid(transform.translation),
+                stats: stats.clone(),
+                alive: !dead,
+            };
+            (entity, snapshot)
+        })
+        .collect();
+
+    // Rebuild the cell cache when the map is replaced, otherwise only track edits
+    let rebuild = grid.is_changed();
+    if rebuild {
+        state.cells.clear();
+    }
+    for (coord, cell) in cells.iter() {
+        if rebuild || cell.is_changed() {
+            state.cells.insert(*coord, cell.clone());
+        }
+    }
+
+    if regions.is_changed() {
+        state.regions = regions.iter().cloned().collect();
+    }
+    if terrains.is_changed() {
+        state.terrains = terrains.iter().map(|terrain| terrain.id.clone()).collect();
+    }
+}
+
+/// Load the script of a newly loaded map and hot-reload it when the file changes
+fn load_map_script(
+    mut runtime: ResMut<ScriptRuntime>,
+    loaded_map: Res<LoadedMap>,
+    asset_server: Res<AssetServer>,
+    scripts: Res<Assets<MapScript>>,
+    mut asset_events: EventReader<AssetEvent<MapScript>>,
+) {
+    if !loaded_map.loaded {
+        asset_events.clear();
+        return;
+    }
+
+    if runtime.map_name != loaded_map.name {
+        runtime.map_name = loaded_map.name.clone();
+        runtime.script = None;
+        // Most maps have no script, which the asset server would report as an error
+        let path = MapFile::script_path(&loaded_map.name);
+        runtime.source = path.exists().then(|| asset_server.load(MapFile::script_asset_path(&loaded_map.name)));
+    }
+
+    let Some(source) = runtime.source.as_ref().map(Handle::id) else {
+        asset_events.clear();
+        return;
+    };
+    let (mut loaded, mut modified) = (false, false);
+    for event in asset_events.read() {
+        loaded |= event.is_loaded_with_dependencies(source);
+        modified |= event.is_modified(source);
+    }
+    // A file that fails to load keeps the previous version running
+    let Some(text) = scripts.get(source).map(|script| script.text.as_str()) else {
+        return;
+    };
+    let path = MapFile::script_path(&loaded_map.name);
+
+    if modified && let Some(previous) = runtime.script.as_ref() {
+        let globals = previous.globals.clone();
+        match runtime.compile(path, text, globals) {
+            Ok(script) => {
+                info!("Reloaded map script {}", script.path.display());
+                let ScriptRuntime { engine, script: slot, .. } = &mut *runtime;
+                slot.insert(script).call(engine, "on_reload", ());
+            }
+            // Keep running the previous version until the file is fixed
+            Err(error) => error!("{error}"),
+        }
+    } else if loaded && runtime.script.is_none() {
+        match runtime.compile(path, text, Dynamic::from(Map::new())) {
+            Ok(script) => {
+                info!("Loaded map script {}", script.path.display());
+                let ScriptRuntime { engine, script: slot, .. } = &mut *runtime;
+                slot.insert(script).call(engine, "on_map_start", ());
+            }
+            Err(error) => error!("{error}"),
+        }
+    }
+}
+
+/// Pass this frame's game events to the script's handlers
+fn dispatch_script_events(
+    mut runtime: ResMut<ScriptRuntime>,
+    time: Res<Time>,
+    mut move_events: EventReader<UnitMoveEvent>,
+    mut entered_events: EventReader<UnitEnteredRegion>,
+    mut left_events: EventReader<UnitLeftRegion>,
+    mut building_events: EventReader<BuildingPlacedEvent>,
+    mut death_events: EventReader<UnitDiedEvent>,
+) {
+    let ScriptRuntime { engine, script, .. } = &mut *runtime;
+    let Some(script) = script.as_mut() else {
+        // Drain the readers so a script loaded later doesn't see stale events
+        move_events.clear();
+        entered_events.clear();
+        left_events.clear();
+        building_events.clear();
+        death_events.clear();
+        return;
+    };
+
+    script.call(engine, "on_tick", (time.delta_secs() as FLOAT,));
+    for event in move_events.read() {
+        let args = (entity_id(event.entity), event.to.x as i64, event.to.y as i64);
+        script.call(engine, "on_unit_moves", args);
+    }
+    for event in entered_events.read() {
+        script.call(engine, "on_unit_enters_region", (entity_id(event.entity), event.region.clone()));
+    }
+    for event in left_events.read() {
+        script.call(engine, "on_unit_leaves_region", (entity_id(event.entity), event.region.clone()));
+    }
+    for event in building_events.read() {
+        script.call(engine, "on_building_placed", (entity_id(event.entity),));
+    }
+    for event in death_events.read() {
+        script.call(engine, "on_unit_dies", (entity_id(event.entity),));
+    }
+}
+
+/// Apply the world changes scripts queued this frame
+fn apply_script_commands(
+    runtime: Res<ScriptRuntime>,
+    mut effects: ScriptEffects,
+    grid: Res<MapGrid>,
+    time: Res<Time>,
+) {
+    let mut state = runtime.state.lock().unwrap();
+    let timestamp = time.elapsed_secs_f64();
+
+    for command in std::mem::take(&mut state.commands) {
+        match command {
+            ScriptCommand::SpawnUnit { name, unit_type, faction, coord } => {
+                let team = match faction {
+                    FactionId::Player(player) => TeamId::Team(player),
+                    _ => TeamId::Neutral,
+                };
+                let controller_type = match faction {
+                    FactionId::Player(_) => ControllerType::Human,
+                    _ => ControllerType::Automatic,
+                };
+                let ownership = Ownership { faction, team, controller_type };
+                spawn_unit(&mut effects.commands, &mut effects.unit_assets, &grid, coord, &name, unit_type, ownership);
+            }
+            ScriptCommand::SetStat { entity, stat, value } => {
+                if let Ok(mut stats) = effects.stats.get_mut(entity) {
+                    stats.set_stat(&stat, value);
+                }
+            }
+            ScriptCommand::MoveUnit { entity, to } => {
+                if state.units.contains_key(&entity) {
+                    effects.order_events.write(IssueOrderEvent { entity, order: Order::Move(to), queued: false, formation: default() });
+                }
+            }
+            ScriptCommand::SetTerrain { coord, terrain } => {
+                effects.terrain_events.write(TerrainModifiedEvent::terrain(coord, terrain, timestamp));
+            }
+            ScriptCommand::SetCell { coord, cell } => {
+                effects.terrain_events.write(TerrainModifiedEvent::set_cell(coord, &cell, timestamp));
+            }
+            ScriptCommand::ShowMessage { text, seconds } => {
+                effects.messages.messages.push((text, seconds));
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use crate::components::unit::{UnitType, Statsheet};
+use crate::components::faction::{Ownership, FactionId, TeamId, ControllerType};
+use crate::plugins::map::{GridCoord, TerrainType};
+
+/// A map trigger: when `event` happens and all `conditions` hold, run `actions`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trigger {
+    pub name: String,
+    pub event: TriggerEvent,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    #[serde(default)]
+    pub actions: Vec<Action>,
+    /// Disable the trigger after it fires once
+    #[serde(default)]
+    pub once: bool,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// Game events a trigger can listen to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TriggerEvent {
+    /// Once, when the map starts
+    MapStart,
+    /// After `seconds`, optionally repeating
+    Timer { seconds: f32, repeat: bool },
+    /// A unit moves into a new cell
+    UnitMoves,
+    /// A unit enters the named region
+    UnitEntersRegion(String),
+    /// A unit leaves the named region
+    UnitLeavesRegion(String),
+    /// A building is placed
+    BuildingPlaced,
+    /// A faction sees terrain it had never seen before
+    TerrainRevealed,
+    /// A unit dies
+    UnitDies,
+}
+
+/// Unit statistics conditions can compare
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stat {
+    Health,
+    HealthPercent,
+    Mana,
+    Damage,
+    Armor,
+    MoveSpeed,
+    AttackRange,
+    SightRange,
+}
+
+impl Stat {
+    /// Read the stat from a stat sheet
+    pub fn read(&self, stats: &Statsheet) -> f32 {
+        match self {
+            Stat::Health => stats.health,
+            Stat::HealthPercent => {
+                if stats.max_health > 0.0 {
+                    stats.health / stats.max_health * 100.0
+                } else {
+                    0.0
+                }
+            }
+            Stat::Mana => stats.mana,
+            Stat::Damage => stats.damage,
+            Stat::Armor => stats.armor,
+            Stat::MoveSpeed => stats.move_speed,
+            Stat::AttackRange => stats.attack_range,
+            Stat::SightRange => stats.sight_range,
+        }
+    }
+}
+
+/// Checks made against the triggering unit (or faction) before actions run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Condition {
+    OwnerIs(FactionId),
+    TeamIs(TeamId),
+    ControllerIs(ControllerType),
+    UnitTypeIs(UnitType),
+    StatAtLeast(Stat, f32),
+    StatBelow(Stat, f32),
+    Not(Box<Condition>),
+    AnyOf(Vec<Condition>),
+}
+
+/// What a trigger is evaluated against
+#[derive(Debug, Clone, Default)]
+pub struct TriggerContext {
+    pub unit: Option<bevy::prelude::Entity>,
+    pub faction: Option<FactionId>,
+    pub coord: Option<GridCoord>,
+}
+
+/// Unit data conditions are checked against
+pub struct ConditionSubject<'a> {
+    pub unit_type: Option<&'a UnitType>,
+    pub ownership: Option<&'a Ownership>,
+    pub stats: Option<&'a Statsheet>,
+    /// Faction of the event when there is no unit (e.g. terrain revealed)
+    pub faction: Option<&'a FactionId>,
+}
+
+impl Condition {
+    /// Evaluate the condition; unit conditions fail when there is no unit
+    pub fn check(&self, subject: &ConditionSubject) -> bool {
+        match self {
+            Condition::OwnerIs(faction) => subject
+                .ownership
+                .map(|ownership| &ownership.faction)
+                .or(subject.faction)
+                .is_some_and(|owner| owner == faction),
+            Condition::TeamIs(team) => subject.ownership.is_some_and(|ownership| ownership.team == *team),
+            Condition::ControllerIs(controller) => {
+                subject.ownership.is_some_and(|ownership| ownership.controller_type == *controller)
+            }
+            Condition::UnitTypeIs(unit_type) => subject.unit_type == Some(unit_type),
+            Condition::StatAtLeast(stat, value) => subject.stats.is_some_and(|stats| stat.read(stats) >= *value),
+            Condition::StatBelow(stat, value) => subject.stats.is_some_and(|stats| stat.read(stats) < *value),
+            Condition::Not(condition) => !condition.check(subject),
+            Condition::AnyOf(conditions) => conditions.iter().any(|condition| condition.check(subject)),
+        }
+    }
+}
+
+/// Where a spawn action places units
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SpawnPoint {
+    Cell(GridCoord),
+    /// Center of a named region
+    Region(String),
+    /// Cell of the unit or event that fired the trigger
+    Trigger,
+}
+
+/// Effects a trigger can have
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+    SpawnUnit {
+        name: String,
+        unit_type: UnitType,
+        faction: FactionId,
+        team: TeamId,
+        at: SpawnPoint,
+        #[serde(default = "one")]
+        count: u32,
+    },
+    /// Change the terrain of every cell in a region
+    ModifyTerrain { region: String, terrain: TerrainType },
+    GiveResources { faction: FactionId, gold: i32, lumber: i32 },
+    ShowMessage { text: String, seconds: f32 },
+    EndGame { winner: Option<FactionId> },
+    EnableTrigger(String),
+    DisableTrigger(String),
+}
+
+fn one() -> u32 {
+    1
+}
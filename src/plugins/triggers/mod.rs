@@ -0,0 +1,350 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use crate::components::unit::{Unit, Statsheet};
+use crate::components::faction::{Ownership, FactionId, ControllerType, LocalPlayer, PlayerResources};
+use crate::plugins::map::{
+    spawn_unit, BuildingPlacedEvent, MapGrid, RegionRegistry, TerrainModifiedEvent, UnitAssets,
+    UnitEnteredRegion, UnitLeftRegion, UnitMoveEvent,
+};
+use crate::plugins::fog::TerrainExploredEvent;
+use crate::plugins::units::UnitDiedEvent;
+
+pub mod data;
+
+pub use data::{Action, SpawnPoint, Trigger, TriggerEvent};
+use data::{ConditionSubject, TriggerContext};
+
+/// Event for when a trigger ends the game
+#[derive(Event)]
+pub struct GameOverEvent {
+    pub winner: Option<FactionId>,
+}
+
+/// Runtime state of one trigger
+#[derive(Debug, Clone)]
+struct TriggerState {
+    trigger: Trigger,
+    enabled: bool,
+    /// Seconds accumulated towards a timer event
+    elapsed: f32,
+}
+
+/// Resource holding the current map's triggers
+#[derive(Resource, Debug, Default)]
+pub struct TriggerSet {
+    triggers: Vec<TriggerState>,
+    /// Whether map start triggers have run
+    started: bool,
+}
+
+impl TriggerSet {
+    pub fn new(triggers: Vec<Trigger>) -> Self {
+        Self {
+            triggers: triggers
+                .into_iter()
+                .map(|trigger| TriggerState {
+                    enabled: trigger.enabled,
+                    trigger,
+                    elapsed: 0.0,
+                })
+                .collect(),
+            started: false,
+        }
+    }
+
+    /// Trigger definitions, as saved in the map file
+    pub fn definitions(&self) -> Vec<Trigger> {
+        self.triggers.iter().map(|state| state.trigger.clone()).collect()
+    }
+
+    /// Enable or disable every trigger with the given name
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        for state in self.triggers.iter_mut().filter(|state| state.trigger.name == name) {
+            state.enabled = enabled;
+            state.elapsed = 0.0;
+        }
+    }
+}
+
+/// Action lists queued by fired triggers, waiting to be executed
+#[derive(Resource, Default)]
+struct PendingActions(Vec<(Vec<Action>, TriggerContext)>);
+
+/// Messages shown on screen by triggers
+#[derive(Resource, Default)]
+pub struct GameMessages {
+    /// Message text and seconds left on screen
+    pub messages: Vec<(String, f32)>,
+}
+
+/// Marker component for the trigger message text
+#[derive(Component)]
+struct GameMessageText;
+
+/// System parameter reading every game event triggers can fire on
+#[derive(SystemParam)]
+struct TriggerEventReaders<'w, 's> {
+    moves: EventReader<'w, 's, UnitMoveEvent>,
+    entered: EventReader<'w, 's, UnitEnteredRegion>,
+    left: EventReader<'w, 's, UnitLeftRegion>,
+    buildings: EventReader<'w, 's, BuildingPlacedEvent>,
+    explored: EventReader<'w, 's, TerrainExploredEvent>,
+    deaths: EventReader<'w, 's, UnitDiedEvent>,
+}
+
+/// System parameter with everything trigger actions change
+#[derive(SystemParam)]
+struct ActionTargets<'w, 's> {
+    commands: Commands<'w, 's>,
+    unit_assets: UnitAssets<'w>,
+    triggers: ResMut<'w, TriggerSet>,
+    resources: ResMut<'w, PlayerResources>,
+    messages: ResMut<'w, GameMessages>,
+    terrain_events: EventWriter<'w, TerrainModifiedEvent>,
+    game_over_events: EventWriter<'w, GameOverEvent>,
+}
+
+/// Map trigger plugin
+pub struct TriggerPlugin;
+
+impl Plugin for TriggerPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<GameOverEvent>()
+            .init_resource::<TriggerSet>()
+            .init_resource::<PendingActions>()
+            .init_resource::<GameMessages>()
+            .init_resource::<PlayerResources>()
+            .add_systems(Startup, setup_message_text)
+            .add_systems(Update, (
+                fire_triggers,
+                execute_actions,
+                announce_game_over,
+                update_message_text,
+            ).chain());
+    }
+}
+
+/// Collect this frame's game events, match them against triggers and queue actions
+fn fire_triggers(
+    mut triggers: ResMut<TriggerSet>,
+    mut pending: ResMut<PendingActions>,
+    time: Res<Time>,
+    mut events: TriggerEventReaders,
+    units: Query<(Option<&Unit>, Option<&Ownership>, Option<&Statsheet>)>,
+) {
+    let mut occurrences: Vec<(TriggerEvent, TriggerContext)> = Vec::new();
+    let unit_context = |entity: Entity, coord| TriggerContext {
+        unit: Some(entity),
+        coord: Some(coord),
+        ..default()
+    };
+
+    for event in events.moves.read() {
+        occurrences.push((TriggerEvent::UnitMoves, unit_context(event.entity, event.to)));
+    }
+    for event in events.entered.read() {
+        occurrences.push((TriggerEvent::UnitEntersRegion(event.region.clone()), unit_context(event.entity, event.coord)));
+    }
+    for event in events.left.read() {
+        occurrences.push((TriggerEvent::UnitLeavesRegion(event.region.clone()), unit_context(event.entity, event.coord)));
+    }
+    for event in events.buildings.read() {
+        let context = TriggerContext { faction: Some(event.faction.clone()), ..unit_context(event.entity, event.position) };
+        occurrences.push((TriggerEvent::BuildingPlaced, context));
+    }
+    for event in events.explored.read() {
+        let context = TriggerContext {
+            faction: Some(event.faction.clone()),
+            coord: Some(event.center),
+            ..default()
+        };
+        occurrences.push((TriggerEvent::TerrainRevealed, context));
+    }
+    for event in events.deaths.read() {
+        let context = TriggerContext { faction: Some(event.faction.clone()), ..unit_context(event.entity, event.coord) };
+        occurrences.push((TriggerEvent::UnitDies, context));
+    }
+
+    // Map start and timers fire on their own schedule
+    let starting = !triggers.started;
+    triggers.started = true;
+    let delta = time.delta_secs();
+
+    for state in triggers.triggers.iter_mut() {
+        if !state.enabled {
+            continue;
+        }
+
+        let mut contexts: Vec<TriggerContext> = occurrences
+            .iter()
+            .filter(|(event, _)| *event == state.trigger.event)
+            .map(|(_, context)| context.clone())
+            .collect();
+
+        match state.trigger.event {
+            TriggerEvent::MapStart if starting => contexts.push(TriggerContext::default()),
+            TriggerEvent::Timer { seconds, repeat } => {
+                state.elapsed += delta;
+                if state.elapsed >= seconds {
+                    contexts.push(TriggerContext::default());
+                    if repeat {
+                        state.elapsed -= seconds.max(f32::EPSILON);
+                    } else {
+                        state.enabled = false;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        for context in contexts {
+            let (unit, ownership, stats) = context
+                .unit
+                .and_then(|entity| units.get(entity).ok())
+                .unwrap_or((None, None, None));
+            let subject = ConditionSubject {
+                unit_type: unit.map(|unit| &unit.unit_type),
+                ownership,
+                stats,
+                faction: context.faction.as_ref(),
+            };
+            if !state.trigger.conditions.iter().all(|condition| condition.check(&subject)) {
+                continue;
+            }
+
+            pending.0.push((state.trigger.actions.clone(), context));
+            if state.trigger.once {
+                state.enabled = false;
+                break;
+            }
+        }
+    }
+}
+
+/// Run queued trigger actions
+fn execute_actions(
+    mut targets: ActionTargets,
+    grid: Res<MapGrid>,
+    regions: Res<RegionRegistry>,
+    mut pending: ResMut<PendingActions>,
+    time: Res<Time>,
+) {
+    let timestamp = time.elapsed_secs_f64();
+
+    for (actions, context) in std::mem::take(&mut pending.0) {
+        for action in actions {
+            match action {
+                Action::SpawnUnit { name, unit_type, faction, team, at, count } => {
+                    let coord = match &at {
+                        SpawnPoint::Cell(coord) => Some(*coord),
+                        SpawnPoint::Region(region) => regions.get(region).and_then(|region| region.center()),
+                        SpawnPoint::Trigger => context.coord,
+                    };
+                    let Some(coord) = coord else {
+                        warn!("Trigger spawn point {:?} could not be resolved", at);
+                        continue;
+                    };
+                    let controller_type = match faction {
+                        FactionId::Player(_) => ControllerType::Human,
+                        _ => ControllerType::Automatic,
+                    };
+                    for _ in 0..count {
+                        let ownership = Ownership {
+                            faction: faction.clone(),
+                            team: team.clone(),
+                            controller_type: controller_type.clone(),
+                        };
+                        spawn_unit(
+                            &mut targets.commands,
+                            &mut targets.unit_assets,
+                            &grid,
+                            coord,
+                            &name,
+                            unit_type.clone(),
+                            ownership,
+                        );
+                    }
+                }
+                Action::ModifyTerrain { region, terrain } => {
+                    let Some(region) = regions.get(&region) else {
+                        warn!("Trigger references unknown region {}", region);
+                        continue;
+                    };
+                    for coord in region.cells() {
+                        if grid.in_bounds(coord) {
+                            targets.terrain_events.write(TerrainModifiedEvent::terrain(coord, terrain.clone(), timestamp));
+                        }
+                    }
+                }
+                Action::GiveResources { faction, gold, lumber } => {
+                    targets.resources.give(&faction, gold, lumber);
+                }
+                Action::ShowMessage { text, seconds } => {
+                    targets.messages.messages.push((text, seconds));
+                }
+                Action::EndGame { winner } => {
+                    targets.game_over_events.write(GameOverEvent { winner });
+                }
+                Action::EnableTrigger(name) => targets.triggers.set_enabled(&name, true),
+                Action::DisableTrigger(name) => targets.triggers.set_enabled(&name, false),
+            }
+        }
+    }
+}
+
+/// Tell the local player whether they won or lost
+fn announce_game_over(
+    mut events: EventReader<GameOverEvent>,
+    local_player: Res<LocalPlayer>,
+    mut messages: ResMut<GameMessages>,
+) {
+    for event in events.read() {
+        let text = match &event.winner {
+            Some(winner) if *winner == local_player.faction => "Victory!",
+            Some(_) => "Defeat",
+            None => "Game over",
+        };
+        messages.messages.push((text.to_string(), f32::INFINITY));
+    }
+}
+
+fn setup_message_text(mut commands: Commands) {
+    commands.spawn((
+        Text::new(""),
+        TextLayout::new_with_justify(JustifyText::Center),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(40.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        GameMessageText,
+    ));
+}
+
+/// Expire old messages and show the rest
+fn update_message_text(
+    mut messages: ResMut<GameMessages>,
+    time: Res<Time>,
+    mut texts: Query<&mut Text, With<GameMessageText>>,
+) {
+    let delta = time.delta_secs();
+    for (_, remaining) in messages.messages.iter_mut() {
+        *remaining -= delta;
+    }
+    messages.messages.retain(|(_, remaining)| *remaining > 0.0);
+
+    let content = messages
+        .messages
+        .iter()
+        .map(|(text, _)| text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    for mut text in texts.iter_mut() {
+        if text.0 != content {
+            text.0 = content.clone();
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use bevy::prelude::*;
+use crate::components::faction::Ownership;
+use crate::components::unit::{MovePath, Statsheet, Steering, Unit, UnitType};
+use crate::plugins::map::{OwnershipFilter, SpatialIndex};
+use super::Active;
+
+/// Seconds ahead moving units look for collisions with each other
+const TIME_HORIZON: f32 = 1.5;
+/// Distance from a unit's center within which others are avoided
+const NEIGHBOR_DISTANCE: f32 = 3.0;
+/// Most neighbors each unit avoids, nearest first, keeping dense crowds cheap
+const MAX_NEIGHBORS: usize = 10;
+/// Extra gap idle units keep from allies walking past them
+const PUSH_MARGIN: f32 = 0.1;
+/// Speed of an idle unit being pushed aside, per unit of overlap
+const PUSH_RATE: f32 = 8.0;
+
+/// Half-plane of allowed velocities: everything left of `direction` through `point`
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    point: Vec2,
+    direction: Vec2,
+}
+
+/// What avoidance needs to know about a unit
+#[derive(Clone, Copy)]
+struct Agent<'a> {
+    position: Vec2,
+    velocity: Vec2,
+    radius: f32,
+    moving: bool,
+    building: bool,
+    ownership: &'a Ownership,
+}
+
+type AgentItem<'a> = (Entity, &'a Unit, &'a Statsheet, &'a Ownership, &'a Transform, &'a Steering, bool);
+/// Avoidance's query of each unit, which yields an `AgentItem` when read only
+type AgentQuery<'a> = (Entity, &'a Unit, &'a Statsheet, &'a Ownership, &'a Transform, &'a mut Steering, Has<MovePath>);
+
+impl<'a> From<AgentItem<'a>> for Agent<'a> {
+    fn from((_, unit, stats, ownership, transform, steering, moving): AgentItem<'a>) -> Self {
+        Self {
+            position: Vec2::new(transform.translation.x, transform.translation.z),
+            velocity: steering.velocity,
+            radius: stats.collision_radius,
+            moving,
+            building: unit.unit_type == UnitType::Building,
+            ownership,
+        }
+    }
+}
+
+/// Pick each unit's velocity for this frame. Moving units keep as close to their
+/// preferred velocity as they can without colliding, using optimal reciprocal collision
+/// avoidance (ORCA) against nearby units; idle units make way for allies walking into them.
+pub fn avoid_collisions(
+    time: Res<Time>,
+    index: Res<SpatialIndex>,
+    mut units: Query<AgentQuery, Active>,
+) {
+    let delta = time.delta_secs();
+    if delta <= 0.0 {
+        return;
+    }
+
+    let mut velocities = Vec::new();
+    for item in units.iter() {
+        let (entity, max_speed, preferred) = (item.0, item.2.move_speed, item.5.preferred);
+        let this = Agent::from(item);
+        if this.building {
+            velocities.push((entity, Vec2::ZERO));
+            continue;
+        }
+
+        // Idle units only make way for allies, so they needn't look at anyone else
+        let center = Vec3::new(this.position.x, 0.0, this.position.y);
+        let filter = if this.moving { OwnershipFilter::Any } else { OwnershipFilter::AlliedWith(this.ownership.clone()) };
+        let neighbors = index
+            .nearest(center, MAX_NEIGHBORS + 1, NEIGHBOR_DISTANCE, &filter)
+            .into_iter()
+            .filter(|neighbor| *neighbor != entity)
+            .filter_map(|neighbor| units.get(neighbor).ok().map(Agent::from));
+
+        let velocity = if this.moving {
+            let lines: Vec<Line> = neighbors
+                // Idle allies step aside instead of being walked around
+                .filter(|other| other.moving || other.building || !other.ownership.is_allied_with(this.ownership))
+                .map(|other| orca_line(&this, &other, delta))
+                .collect();
+            match linear_program2(&lines, max_speed, preferred, false) {
+                Ok(velocity) => velocity,
+                Err((failed, velocity)) => linear_program3(&lines, failed, max_speed, velocity),
+            }
+        } else {
+            let push: Vec2 = neighbors
+                .filter(|other| other.moving)
+                .map(|other| push_aside(&this, &other))
+                .sum();
+            (push * PUSH_RATE).clamp_length_max(max_speed)
+        };
+        velocities.push((entity, velocity));
+    }
+
+    for (entity, velocity) in velocities {
+        if let Ok(mut item) = units.get_mut(entity) {
+            item.5.velocity = velocity;
+        }
+    }
+}
+
+/// Overlap-scaled push moving an idle unit out of a walking ally's way, to the side of
+/// the ally's heading rather than straight ahead of it
+fn push_aside(idle: &Agent, walker: &Agent) -> Vec2 {
+    let relative = idle.position - walker.position;
+    let overlap = idle.radius + walker.radius + PUSH_MARGIN - relative.length();
+    if overlap <= 0.0 {
+        return Vec2::ZERO;
+    }
+    let Some(heading) = walker.velocity.try_normalize() else {
+        return relative.normalize_or_zero() * overlap;
+    };
+    let side = heading.perp();
+    let side = if relative.dot(side) >= 0.0 { side } else { -side };
+    side * overlap
+}
+
+/// ORCA half-plane of velocities that keep `this` clear of `other` for the time horizon.
+/// Moving units share the avoidance effort; units standing still leave it all to `this`.
+fn orca_line(this: &Agent, other: &Agent, delta: f32) -> Line {
+    let relative_position = other.position - this.position;
+    let relative_velocity = this.velocity - other.velocity;
+    let distance_sq = relative_position.length_squared();
+    let combined_radius = this.radius + other.radius;
+    let combined_radius_sq = combined_radius * combined_radius;
+    let responsibility = if other.moving { 0.5 } else { 1.0 };
+
+    let (direction, u) = if distance_sq > combined_radius_sq {
+        // Vector from the cutoff center of the velocity obstacle to the relative velocity
+        let inverse_horizon = 1.0 / TIME_HORIZON;
+        let w = relative_velocity - inverse_horizon * relative_position;
+        let w_length_sq = w.length_squared();
+        let dot = w.dot(relative_position);
+
+        if dot < 0.0 && dot * dot > combined_radius_sq * w_length_sq {
+            // Closest to the cutoff circle
+            let w_length = w_length_sq.sqrt();
+            let unit_w = w / w_length;
+            (Vec2::new(unit_w.y, -unit_w.x), (combined_radius * inverse_horizon - w_length) * unit_w)
+        } else {
+            // Closest to one of the obstacle's legs
+            let leg = (distance_sq - combined_radius_sq).sqrt();
+            let (x, y) = (relative_position.x, relative_position.y);
+            let direction = if relative_position.perp_dot(w) > 0.0 {
+                Vec2::new(x * leg - y * combined_radius, x * combined_radius + y * leg) / distance_sq
+            } else {
+                -Vec2::new(x * leg + y * combined_radius, -x * combined_radius + y * leg) / distance_sq
+            };
+            (direction, relative_velocity.dot(direction) * direction - relative_velocity)
+        }
+    } else {
+        // Already overlapping: get apart within this frame
+        let inverse_step = 1.0 / delta;
+        let w = relative_velocity - inverse_step * relative_position;
+        let w_length = w.length();
+        let unit_w = if w_length > f32::EPSILON { w / w_length } else { Vec2::X };
+        (Vec2::new(unit_w.y, -unit_w.x), (combined_radius * inverse_step - w_length) * unit_w)
+    };
+
+    Line { point: this.velocity + responsibility * u, direction }
+}
+
+/// Best velocity on line `line_index` within `radius` that satisfies all earlier lines
+fn linear_program1(lines: &[Line], line_index: usize, radius: f32, optimal: Vec2, direction_optimal: bool) -> Option<Vec2> {
+    let line = lines[line_index];
+    let dot = line.point.dot(line.direction);
+    let discriminant = dot * dot + radius * radius - line.point.length_squared();
+    if discriminant < 0.0 {
+        // The speed limit circle misses the line entirely
+        return None;
+    }
+
+    let root = discriminant.sqrt();
+    let mut t_left = -dot - root;
+    let mut t_right = -dot + root;
+    for earlier in &lines[..line_index] {
+        let denominator = line.direction.perp_dot(earlier.direction);
+        let numerator = earlier.direction.perp_dot(line.point - earlier.point);
+        if denominator.abs() <= f32::EPSILON {
+            // Parallel lines: either this one lies fully outside, or the earlier one adds nothing
+            if numerator < 0.0 {
+                return None;
+            }
+            continue;
+        }
+
+        let t = numerator / denominator;
+        if denominator >= 0.0 {
+            t_right = t_right.min(t);
+        } else {
+            t_left = t_left.max(t);
+        }
+        if t_left > t_right {
+            return None;
+        }
+    }
+
+    let t = if direction_optimal {
+        if optimal.dot(line.direction) > 0.0 { t_right } else { t_left }
+    } else {
+        line.direction.dot(optimal - line.point).clamp(t_left, t_right)
+    };
+    Some(line.point + t * line.direction)
+}
+
+/// Velocity within `radius` closest to `optimal` (or furthest along it, when
+/// `direction_optimal`) that satisfies every line. On failure, returns the index of the
+/// first line that couldn't be satisfied with the best velocity found up to it.
+fn linear_program2(lines: &[Line], radius: f32, optimal: Vec2, direction_optimal: bool) -> Result<Vec2, (usize, Vec2)> {
+    let mut result = if direction_optimal {
+        optimal * radius
+    } else {
+        optimal.clamp_length_max(radius)
+    };
+
+    for (index, line) in lines.iter().enumerate() {
+        if line.direction.perp_dot(line.point - result) > 0.0 {
+            match linear_program1(lines, index, radius, optimal, direction_optimal) {
+                Some(velocity) => result = velocity,
+                None => return Err((index, result)),
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Fallback when no velocity satisfies every line: the velocity that violates them the
+/// least, starting from the first failed line
+fn linear_program3(lines: &[Line], begin: usize, radius: f32, mut result: Vec2) -> Vec2 {
+    let mut distance = 0.0;
+    for (index, line) in lines.iter().enumerate().skip(begin) {
+        if line.direction.perp_dot(line.point - result) <= distance {
+            continue;
+        }
+
+        let projected: Vec<Line> = lines[..index]
+            .iter()
+            .filter_map(|earlier| {
+                let determinant = line.direction.perp_dot(earlier.direction);
+                let point = if determinant.abs() <= f32::EPSILON {
+                    // Lines pointing the same way can't be violated more than this one
+                    if line.direction.dot(earlier.direction) > 0.0 {
+                        return None;
+                    }
+                    0.5 * (line.point + earlier.point)
+                } else {
+                    line.point
+                        + (earlier.direction.perp_dot(line.point - earlier.point) / determinant) * line.direction
+                };
+                Some(Line { point, direction: (earlier.direction - line.direction).normalize() })
+            })
+            .collect();
+
+        let optimal = Vec2::new(-line.direction.y, line.direction.x);
+        if let Ok(velocity) = linear_program2(&projected, radius, optimal, true) {
+            result = velocity;
+        }
+        distance = line.direction.perp_dot(line.point - result);
+    }
+    result
+}
@@ -0,0 +1,359 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use crate::components::unit::{MovePath, Statsheet, Unit, UnitType};
+use crate::plugins::map::{GridCells, GridCoord, MovementClass, PathfindingRequestEvent, PathfindingResultEvent};
+use super::Corpse;
+
+/// Distance between neighboring formation slots, in cells
+const SLOT_SPACING: f32 = 1.0;
+/// Members further than this from their slot (in cells) slow the whole group down
+const STRAGGLER_DISTANCE: f32 = 1.5;
+/// Fraction of its speed a group keeps while waiting for stragglers to catch up
+const STRAGGLER_SPEED: f32 = 0.4;
+/// Key that cycles through the formation shapes the local player's orders use
+const CYCLE_KEY: KeyCode = KeyCode::KeyF;
+
+/// Layout a group keeps while moving
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum FormationShape {
+    /// One rank abreast
+    Line,
+    /// Ranks and files as close to square as the group allows
+    #[default]
+    Box,
+    /// A point at the front, widening by one slot each rank
+    Wedge,
+}
+
+impl FormationShape {
+    /// Shape after this one when cycling
+    pub fn next(self) -> Self {
+        match self {
+            FormationShape::Line => FormationShape::Box,
+            FormationShape::Box => FormationShape::Wedge,
+            FormationShape::Wedge => FormationShape::Line,
+        }
+    }
+
+    /// Slot offsets in cells for a group of `count`, front rank first: `x` to the
+    /// right of the heading, `y` along it
+    pub fn slots(self, count: usize) -> Vec<Vec2> {
+        let mut slots = Vec::with_capacity(count);
+        match self {
+            FormationShape::Line => slots.extend(rank(0, count)),
+            FormationShape::Box => {
+                let files = (count as f32).sqrt().ceil().max(1.0) as usize;
+                let mut ranked = 0;
+                while ranked < count {
+                    let size = files.min(count - ranked);
+                    slots.extend(rank(slots.len() / files, size));
+                    ranked += size;
+                }
+            }
+            FormationShape::Wedge => {
+                let mut index = 0;
+                while slots.len() < count {
+                    let size = (index + 1).min(count - slots.len());
+                    slots.extend(rank(index, size));
+                    index += 1;
+                }
+            }
+        }
+
+        // Center the formation on its middle rank so the group is built around its position
+        let depth = slots.iter().map(|slot| -slot.y).fold(0.0, f32::max);
+        slots.iter_mut().for_each(|slot| slot.y += depth / 2.0);
+        slots
+    }
+}
+
+/// Offsets of `size` slots abreast in rank `index`, counted back from the front
+fn rank(index: usize, size: usize) -> impl Iterator<Item = Vec2> {
+    let y = -(index as f32) * SLOT_SPACING;
+    (0..size).map(move |file| Vec2::new((file as f32 - (size - 1) as f32 / 2.0) * SLOT_SPACING, y))
+}
+
+/// Formation the local player's groups move in
+#[derive(Resource, Debug, Default)]
+pub struct FormationChoice(pub FormationShape);
+
+/// Cycle the local player's formation with the keybinding
+pub fn cycle_formation(keys: Res<ButtonInput<KeyCode>>, mut choice: ResMut<FormationChoice>) {
+    if keys.just_pressed(CYCLE_KEY) {
+        choice.0 = choice.0.next();
+        info!("Formation: {:?}", choice.0);
+    }
+}
+
+/// Order for several units to move to a cell together, keeping a formation
+#[derive(Event)]
+pub struct GroupMoveEvent {
+    pub units: Vec<Entity>,
+    pub to: GridCoord,
+    pub shape: FormationShape,
+    pub timestamp: f64,
+}
+
+/// A group moving in formation. Its entity is an invisible leader walking the group's
+/// path at the speed of the slowest member, with members following their slots around it.
+#[derive(Component, Debug, Clone)]
+pub struct Formation {
+    pub members: Vec<Entity>,
+    /// Direction the formation faces, on the ground plane
+    pub facing: Vec2,
+}
+
+/// Place of a unit in a formation, as an offset in cells from the leader
+#[derive(Component, Debug, Clone, Copy)]
+pub struct FormationSlot {
+    pub formation: Entity,
+    pub offset: Vec2,
+}
+
+/// Set up a formation for each group move order and ask for the leader's path
+pub fn start_group_moves(
+    mut commands: Commands,
+    cells: GridCells,
+    mut orders: EventReader<GroupMoveEvent>,
+    mut path_requests: EventWriter<PathfindingRequestEvent>,
+    units: Query<(&Unit, &Transform, Option<&FormationSlot>), Without<Corpse>>,
+    mut formations: Query<&mut Formation>,
+) {
+    let grid = &cells.grid;
+    for order in orders.read() {
+        let members: Vec<(Entity, &Unit, Vec2)> = order
+            .units
+            .iter()
+            .filter_map(|entity| units.get(*entity).ok().map(|(unit, transform, _)| (*entity, unit, transform)))
+            .filter(|(_, unit, _)| unit.unit_type != UnitType::Building)
+            .map(|(entity, unit, transform)| (entity, unit, Vec2::new(transform.translation.x, transform.translation.z)))
+            .collect();
+        // Members' orders wait on the formation's path rather than their own, so even a
+        // lone member left of the group walks as a formation
+        if members.is_empty() {
+            continue;
+        }
+
+        // Units leave whatever formation they were in
+        for (entity, _, _) in &members {
+            if let Ok((_, _, Some(slot))) = units.get(*entity)
+                && let Ok(mut formation) = formations.get_mut(slot.formation)
+            {
+                formation.members.retain(|member| member != entity);
+            }
+        }
+
+        let center = members.iter().map(|(_, _, position)| *position).sum::<Vec2>() / members.len() as f32;
+        let target = grid.grid_to_world(order.to);
+        let facing = (Vec2::new(target.x, target.z) - center).try_normalize().unwrap_or(Vec2::NEG_Y);
+        let slots = assign_slots(order.shape, &members, center, facing, grid.cell_size);
+
+        let formation = commands
+            .spawn((
+                Formation {
+                    members: slots.iter().map(|(entity, _)| *entity).collect(),
+                    facing,
+                },
+                Transform::from_xyz(center.x, 0.0, center.y),
+                Name::new("Formation"),
+            ))
+            .id();
+        for (entity, offset) in slots {
+            commands.entity(entity).insert(FormationSlot { formation, offset });
+        }
+
+        // A formation asks for a single path, so its request needs no number
+        path_requests.write(PathfindingRequestEvent {
+            entity: formation,
+            request: 0,
+            from: grid.world_to_grid(Vec3::new(center.x, 0.0, center.y)),
+            to: order.to,
+            movement: MovementClass::Ground,
+            timestamp: order.timestamp,
+        });
+    }
+}
+
+/// Match members to slots: melee units take the front slots, ranged units and casters
+/// the rear, and within each part every unit gets the free slot closest to it, closest
+/// pairs first, keeping the distance walked to form up low
+fn assign_slots(
+    shape: FormationShape,
+    members: &[(Entity, &Unit, Vec2)],
+    center: Vec2,
+    facing: Vec2,
+    cell_size: f32,
+) -> Vec<(Entity, Vec2)> {
+    let slots = &shape.slots(members.len());
+    let is_rear = |unit: &Unit| matches!(unit.unit_type, UnitType::Ranged | UnitType::Caster);
+    let front_count = members.iter().filter(|(_, unit, _)| !is_rear(unit)).count();
+
+    let mut assigned = Vec::with_capacity(members.len());
+    for (rear, slot_range) in [(false, 0..front_count), (true, front_count..slots.len())] {
+        let part: Vec<&(Entity, &Unit, Vec2)> = members.iter().filter(|(_, unit, _)| is_rear(unit) == rear).collect();
+        let mut pairs: Vec<(f32, usize, usize)> = part
+            .iter()
+            .enumerate()
+            .flat_map(|(member, (_, _, position))| {
+                slot_range.clone().map(move |slot| {
+                    let world = slot_position(center, facing, slots[slot], cell_size);
+                    (position.distance_squared(world), member, slot)
+                })
+            })
+            .collect();
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut member_taken = vec![false; part.len()];
+        let mut slot_taken = vec![false; slots.len()];
+        for (_, member, slot) in pairs {
+            if member_taken[member] || slot_taken[slot] {
+                continue;
+            }
+            member_taken[member] = true;
+            slot_taken[slot] = true;
+            assigned.push((part[member].0, slots[slot]));
+        }
+    }
+    assigned
+}
+
+/// World (XZ) position of a slot around a leader
+fn slot_position(leader: Vec2, facing: Vec2, offset: Vec2, cell_size: f32) -> Vec2 {
+    let right = -facing.perp();
+    leader + (right * offset.x + facing * offset.y) * cell_size
+}
+
+/// Hand a formation its leader's path, or break it up if there is none
+pub fn start_formation_paths(
+    mut commands: Commands,
+    cells: GridCells,
+    mut results: EventReader<PathfindingResultEvent>,
+    formations: Query<&Formation>,
+) {
+    for result in results.read() {
+        let Ok(formation) = formations.get(result.entity) else {
+            continue;
+        };
+        if !result.success {
+            dissolve(&mut commands, result.entity, formation);
+            continue;
+        }
+        let waypoints = result.path.iter().skip(1).map(|coord| cells.grid.grid_to_world(*coord)).collect();
+        commands.entity(result.entity).insert(MovePath { waypoints });
+    }
+}
+
+/// Units marching in a formation
+type Marching = (With<Unit>, With<FormationSlot>, Without<Corpse>);
+
+/// Walk each formation's leader along its path at the slowest member's speed, holding
+/// back while members lag, and send members to their slots. Members whose slot lies on
+/// ground they can't stand on (e.g. at a choke point) file in behind the leader one per
+/// slot spacing, so the group passes in single file and spreads out again afterwards.
+pub fn march_formations(
+    mut commands: Commands,
+    cells: GridCells,
+    time: Res<Time>,
+    mut formations: Query<(Entity, &mut Formation, &mut Transform, Option<&mut MovePath>), Without<Unit>>,
+    mut members: Query<(&Statsheet, &Transform, Option<&mut MovePath>), Marching>,
+    slots: Query<&FormationSlot>,
+) {
+    let grid = &cells.grid;
+    let walkable = |position: Vec2| {
+        cells
+            .get(grid.world_to_grid(Vec3::new(position.x, 0.0, position.y)))
+            .is_some_and(|cell| cell.walkable)
+    };
+
+    for (entity, mut formation, mut transform, path) in formations.iter_mut() {
+        // Drop members that died, left for other orders or joined another group
+        formation.members.retain(|member| {
+            members.contains(*member) && slots.get(*member).is_ok_and(|slot| slot.formation == entity)
+        });
+        if formation.members.is_empty() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        // Still waiting for the leader's path
+        let Some(mut path) = path else {
+            continue;
+        };
+
+        let leader = Vec2::new(transform.translation.x, transform.translation.z);
+        let mut slowest = f32::INFINITY;
+        let mut straggling = false;
+        for member in &formation.members {
+            let (Ok((stats, member_transform, _)), Ok(slot)) = (members.get(*member), slots.get(*member)) else {
+                continue;
+            };
+            slowest = slowest.min(stats.move_speed);
+            let position = Vec2::new(member_transform.translation.x, member_transform.translation.z);
+            let target = slot_position(leader, formation.facing, slot.offset, grid.cell_size);
+            straggling |= position.distance(target) > STRAGGLER_DISTANCE * grid.cell_size;
+        }
+
+        // Advance the leader
+        let speed = if straggling { slowest * STRAGGLER_SPEED } else { slowest };
+        let mut travel = speed * time.delta_secs();
+        let mut position = leader;
+        while let Some(waypoint) = path.waypoints.front() {
+            let waypoint = Vec2::new(waypoint.x, waypoint.z);
+            let offset = waypoint - position;
+            let distance = offset.length();
+            if let Some(direction) = offset.try_normalize() {
+                formation.facing = direction;
+            }
+            if distance > travel {
+                position += offset / distance * travel;
+                break;
+            }
+            position = waypoint;
+            travel -= distance;
+            path.waypoints.pop_front();
+        }
+        transform.translation.x = position.x;
+        transform.translation.z = position.y;
+        let arrived = path.waypoints.is_empty();
+
+        // Send members to their slots, lining up the blocked ones in single file behind
+        // the leader, front ranks first
+        let mut file_length = 0.0;
+        for member in &formation.members {
+            let (Ok((_, member_transform, member_path)), Ok(slot)) = (members.get_mut(*member), slots.get(*member)) else {
+                continue;
+            };
+            let slot_target = slot_position(position, formation.facing, slot.offset, grid.cell_size);
+            let target = if walkable(slot_target) {
+                slot_target
+            } else {
+                file_length += SLOT_SPACING;
+                slot_position(position, formation.facing, Vec2::new(0.0, -file_length), grid.cell_size)
+            };
+            let waypoint = Vec3::new(target.x, member_transform.translation.y, target.y);
+
+            match member_path {
+                Some(mut member_path) => member_path.waypoints = VecDeque::from([waypoint]),
+                None => {
+                    let here = Vec2::new(member_transform.translation.x, member_transform.translation.z);
+                    if here.distance(target) <= f32::EPSILON {
+                        continue;
+                    }
+                    commands.entity(*member).insert(MovePath { waypoints: VecDeque::from([waypoint]) });
+                }
+            }
+        }
+
+        // Members finish walking to their final slots on their own
+        if arrived {
+            dissolve(&mut commands, entity, &formation);
+        }
+    }
+}
+
+fn dissolve(commands: &mut Commands, entity: Entity, formation: &Formation) {
+    for member in &formation.members {
+        commands.entity(*member).remove::<FormationSlot>();
+    }
+    commands.entity(entity).despawn();
+}
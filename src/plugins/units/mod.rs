@@ -0,0 +1,107 @@
+use bevy::prelude::*;
+use crate::components::unit::{Unit, UnitState, UnitType, Statsheet, GroundOffset, Selected};
+use crate::components::faction::{Ownership, FactionId};
+use crate::plugins::editor::EditorState;
+use crate::plugins::map::{Dormant, GridCoord, MapGrid};
+
+mod avoidance;
+mod formation;
+mod movement;
+mod orders;
+mod selection;
+
+pub use formation::GroupMoveEvent;
+pub use orders::{IssueOrderEvent, Order};
+pub use selection::SelectedUnits;
+
+/// Event for when a unit dies
+#[derive(Event)]
+pub struct UnitDiedEvent {
+    pub entity: Entity,
+    pub unit_type: UnitType,
+    pub faction: FactionId,
+    pub coord: GridCoord,
+    pub timestamp: f64,
+}
+
+/// Marker for units whose death has already been handled
+#[derive(Component)]
+pub struct Corpse;
+
+/// Filter for units that are alive and in a loaded chunk
+type Active = (Without<Corpse>, Without<Dormant>);
+
+/// Unit behavior plugin
+pub struct UnitsPlugin;
+
+impl Plugin for UnitsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<UnitDiedEvent>()
+            .add_event::<GroupMoveEvent>()
+            .add_event::<IssueOrderEvent>()
+            .init_resource::<selection::SelectionDrag>()
+            .init_resource::<formation::FormationChoice>()
+            .register_required_components::<Unit, orders::Orders>()
+            .add_systems(Update, (
+                detect_unit_deaths,
+                orders::issue_orders,
+                movement::start_moving,
+                orders::track_order_paths,
+                orders::execute_orders,
+                formation::start_group_moves,
+                formation::start_formation_paths,
+                formation::march_formations,
+                movement::follow_paths,
+                avoidance::avoid_collisions,
+                movement::apply_velocity,
+                orders::sync_unit_state,
+            ).chain())
+            // The editor's brushes own the mouse while it is open
+            .add_systems(Update, (
+                selection::select_units,
+                selection::draw_selection,
+                formation::cycle_formation,
+            ).chain().run_if(in_state(EditorState::Disabled)))
+            // After this frame's movement, before transforms are propagated
+            .add_systems(PostUpdate, follow_ground.before(TransformSystem::TransformPropagate));
+    }
+}
+
+/// Mark units at zero health (or explicitly set to dead) as dead and announce it once
+fn detect_unit_deaths(
+    mut commands: Commands,
+    mut units: Query<(Entity, &mut Unit, &Statsheet, &Ownership, &Transform), Without<Corpse>>,
+    grid: Res<MapGrid>,
+    time: Res<Time>,
+    mut death_events: EventWriter<UnitDiedEvent>,
+) {
+    for (entity, mut unit, stats, ownership, transform) in units.iter_mut() {
+        if stats.health > 0.0 && unit.state != UnitState::Dead {
+            continue;
+        }
+
+        unit.state = UnitState::Dead;
+        unit.target = None;
+        commands.entity(entity).insert(Corpse).remove::<Selected>();
+
+        death_events.write(UnitDiedEvent {
+            entity,
+            unit_type: unit.unit_type.clone(),
+            faction: ownership.faction.clone(),
+            coord: grid.world_to_grid(transform.translation),
+            timestamp: time.elapsed_secs_f64(),
+        });
+    }
+}
+
+/// Keep grounded entities standing on the terrain surface as they move or the ground changes
+fn follow_ground(grid: Res<MapGrid>, mut grounded: Query<(&mut Transform, &GroundOffset), Without<Dormant>>) {
+    for (mut transform, offset) in grounded.iter_mut() {
+        let height = grid.sample_height(transform.translation.x, transform.translation.z) + offset.0;
+        // Only write when it differs, so resting units don't count as changed every frame
+        if (transform.translation.y - height).abs() > f32::EPSILON {
+            transform.translation.y = height;
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use bevy::prelude::*;
+use std::f32::consts::{PI, TAU};
+use crate::components::unit::{MovePath, Statsheet, Steering};
+use crate::plugins::map::{GridCells, PathfindingResultEvent, UnitMoveEvent};
+use super::formation::FormationSlot;
+use super::orders::Orders;
+use super::{Active, Corpse};
+
+/// Units only walk forward once they face their next waypoint within this angle (radians)
+const FACING_TOLERANCE: f32 = PI / 4.0;
+/// Distance at which the final waypoint counts as reached
+const ARRIVAL_DISTANCE: f32 = 0.05;
+/// Distance, in cells, at which waypoints along the way count as passed, so units
+/// pushed off the exact line by others don't circle back to it
+const WAYPOINT_TOLERANCE: f32 = 0.35;
+
+/// Give units the paths found for them. Only the path of the request a unit's orders
+/// still wait on is taken; a stopped or re-ordered unit ignores the ones still on the way.
+/// Units walking their own path leave any formation they were in.
+pub fn start_moving(
+    mut commands: Commands,
+    cells: GridCells,
+    mut results: EventReader<PathfindingResultEvent>,
+    units: Query<&Orders, Without<Corpse>>,
+) {
+    for result in results.read() {
+        let awaited = units.get(result.entity).is_ok_and(|orders| orders.awaits_path(result.request));
+        if !result.success || !awaited {
+            continue;
+        }
+
+        // The first cell is the one the unit stands in
+        let waypoints = result.path.iter().skip(1).map(|coord| cells.grid.grid_to_world(*coord)).collect();
+        commands.entity(result.entity).insert(MovePath { waypoints }).remove::<FormationSlot>();
+    }
+}
+
+/// What path following reads and steers of each unit
+type Walker<'a> = (Entity, &'a Statsheet, &'a mut Transform, &'a mut Steering, Option<&'a mut MovePath>);
+
+/// Turn moving units towards their next waypoint at their turn rate (full turns per
+/// second) and set the velocity they'd like to walk with; arriving units drop their path
+pub fn follow_paths(
+    mut commands: Commands,
+    cells: GridCells,
+    time: Res<Time>,
+    mut units: Query<Walker, Active>,
+) {
+    let delta = time.delta_secs();
+    let waypoint_tolerance = WAYPOINT_TOLERANCE * cells.grid.cell_size;
+    for (entity, stats, mut transform, mut steering, path) in units.iter_mut() {
+        steering.preferred = Vec2::ZERO;
+        let Some(mut path) = path else {
+            continue;
+        };
+
+        let position = Vec2::new(transform.translation.x, transform.translation.z);
+        while let Some(waypoint) = path.waypoints.front() {
+            let tolerance = if path.waypoints.len() > 1 { waypoint_tolerance } else { ARRIVAL_DISTANCE };
+            if position.distance(Vec2::new(waypoint.x, waypoint.z)) > tolerance {
+                break;
+            }
+            path.waypoints.pop_front();
+        }
+        let Some(waypoint) = path.waypoints.front() else {
+            commands.entity(entity).remove::<MovePath>();
+            continue;
+        };
+
+        let offset = Vec2::new(waypoint.x, waypoint.z) - position;
+        let distance = offset.length();
+
+        // Bevy models face -Z, so a yaw of 0 looks towards -Z
+        let (yaw, _, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        let desired = (-offset.x).atan2(-offset.y);
+        let difference = (desired - yaw + PI).rem_euclid(TAU) - PI;
+        let turn = stats.turn_rate * TAU * delta;
+        let step = difference.clamp(-turn, turn);
+        transform.rotation = Quat::from_rotation_y(yaw + step);
+        if (difference - step).abs() > FACING_TOLERANCE {
+            continue;
+        }
+
+        // Slow down for the last stretch instead of overshooting the waypoint
+        let speed = stats.move_speed.min(distance / delta.max(f32::EPSILON));
+        steering.preferred = offset / distance * speed;
+    }
+}
+
+/// Move units by their steering velocity without entering cells they can't step into,
+/// announcing every cell they enter
+pub fn apply_velocity(
+    cells: GridCells,
+    time: Res<Time>,
+    mut units: Query<(Entity, &Statsheet, &mut Transform, &Steering), Active>,
+    mut move_events: EventWriter<UnitMoveEvent>,
+) {
+    let delta = time.delta_secs();
+    for (entity, stats, mut transform, steering) in units.iter_mut() {
+        if steering.velocity == Vec2::ZERO {
+            continue;
+        }
+
+        let from = cells.grid.world_to_grid(transform.translation);
+        let position = Vec2::new(transform.translation.x, transform.translation.z);
+        let step = steering.velocity * delta;
+        // Slide along walls: try the full step, then each axis on its own
+        let candidates = [step, Vec2::new(step.x, 0.0), Vec2::new(0.0, step.y)];
+        let Some(target) = candidates
+            .into_iter()
+            .map(|step| position + step)
+            .find(|target| fits(&cells, position, *target, stats.collision_radius))
+        else {
+            continue;
+        };
+        transform.translation.x = target.x;
+        transform.translation.z = target.y;
+
+        let to = cells.grid.world_to_grid(transform.translation);
+        if to != from {
+            move_events.write(UnitMoveEvent {
+                entity,
+                from,
+                to,
+                timestamp: time.elapsed_secs_f64(),
+            });
+        }
+    }
+}
+
+/// Check if a unit of `radius` can move from `from` to `to` without its body reaching
+/// into a cell it couldn't step into
+fn fits(cells: &GridCells, from: Vec2, to: Vec2, radius: f32) -> bool {
+    let grid = &cells.grid;
+    let at = |point: Vec2| grid.world_to_grid(Vec3::new(point.x, 0.0, point.y));
+    let center = at(from);
+    [Vec2::ZERO, Vec2::X, Vec2::NEG_X, Vec2::Y, Vec2::NEG_Y]
+        .into_iter()
+        .map(|direction| direction * radius)
+        .all(|offset| {
+            let before = at(from + offset);
+            let after = at(to + offset);
+            // Parts already overlapping a blocked cell may stay there while moving out
+            after == before || after == center || cells.can_step(center, after)
+        })
+}
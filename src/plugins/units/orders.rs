@@ -0,0 +1,453 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use crate::components::faction::Ownership;
+use crate::components::unit::{MovePath, Statsheet, Unit, UnitState, UnitType};
+use crate::plugins::map::{
+    GridCells, GridCoord, MovementClass, OwnershipFilter, PathfindingRequestEvent, PathfindingResultEvent,
+    SpatialIndex,
+};
+use super::formation::{FormationShape, FormationSlot, GroupMoveEvent};
+use super::Corpse;
+
+/// Distance, in cells, a following unit keeps from the unit it follows
+const FOLLOW_DISTANCE: f32 = 2.0;
+/// Most orders a unit works through in one frame, when several finish at once
+const MAX_ORDERS_PER_FRAME: usize = 4;
+/// Seconds a unit spends casting a spell once in range
+const CAST_TIME: f32 = 1.0;
+
+/// What a spell is cast at
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CastTarget {
+    None,
+    Unit(Entity),
+    Point(GridCoord),
+}
+
+/// Something a unit is told to do. Orders are plain data, so replays and networking can
+/// record and send them as they are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Order {
+    Move(GridCoord),
+    /// Move, engaging enemies met on the way
+    AttackMove(GridCoord),
+    Attack(Entity),
+    /// Drop every order and stand still
+    Stop,
+    /// Stand still without chasing anything, until told otherwise
+    HoldPosition,
+    /// Walk back and forth between where the order starts and a cell
+    Patrol(GridCoord),
+    Follow(Entity),
+    /// Gather from the resource node on a cell
+    Harvest(GridCoord),
+    Build { unit_type: UnitType, at: GridCoord },
+    Cast { ability: String, target: CastTarget },
+}
+
+impl Order {
+    /// Whether carrying out the order always means walking somewhere
+    fn moves_unit(&self) -> bool {
+        matches!(
+            self,
+            Order::Move(_) | Order::AttackMove(_) | Order::Patrol(_) | Order::Follow(_) | Order::Harvest(_) | Order::Build { .. }
+        )
+    }
+}
+
+/// Event giving a unit an order. A queued order (shift) runs after the unit's current
+/// ones; any other order replaces them.
+#[derive(Event, Debug, Clone, Serialize, Deserialize)]
+pub struct IssueOrderEvent {
+    pub entity: Entity,
+    pub order: Order,
+    pub queued: bool,
+    /// Shape kept when the unit moves together with others given the same order
+    #[serde(default)]
+    pub formation: FormationShape,
+}
+
+/// A unit's orders; the first one is being carried out
+#[derive(Component, Debug, Clone, Default)]
+pub struct Orders {
+    queue: VecDeque<Order>,
+    /// Whether the active order has been started
+    started: bool,
+    /// Number of the path request the active order is waiting on
+    path_request: Option<u32>,
+    /// Path requests made so far. Kept when orders are replaced, so a late path asked
+    /// for by an old order is never taken for the new one's.
+    requests: u32,
+    /// Cell the unit is walking to for the active order
+    destination: Option<GridCoord>,
+    /// Far end of the active patrol's route, where the unit turns around
+    patrol_return: Option<GridCoord>,
+    /// Seconds spent on the active order's action, such as casting, once in range
+    elapsed: f32,
+    /// Formation the unit keeps in group moves, from the last order it was given
+    formation: FormationShape,
+}
+
+impl Orders {
+    /// Order being carried out
+    pub fn active(&self) -> Option<&Order> {
+        self.queue.front()
+    }
+
+    /// Whether the unit is waiting on the path of a request
+    pub fn awaits_path(&self, request: u32) -> bool {
+        self.path_request == Some(request)
+    }
+
+    fn replace(&mut self, order: Order) {
+        *self = Self { requests: self.requests, formation: self.formation, ..default() };
+        self.queue.push_back(order);
+    }
+
+    /// Finish the active order and move on to the next one
+    fn advance(&mut self) {
+        self.queue.pop_front();
+        self.restart();
+    }
+
+    /// Carry out `order` first; the order it interrupts starts over once it is done
+    fn interrupt(&mut self, order: Order) {
+        self.queue.push_front(order);
+        self.restart();
+    }
+
+    /// Forget the progress made on the active order
+    fn restart(&mut self) {
+        self.started = false;
+        self.path_request = None;
+        self.destination = None;
+        self.patrol_return = None;
+        self.elapsed = 0.0;
+    }
+
+    /// Stop walking for the active order and get on with it where the unit stands
+    fn halt(&mut self) {
+        self.started = true;
+        self.path_request = None;
+        self.destination = None;
+    }
+
+    /// Note a walk to `to` for the active order, returning the number to ask for its path with
+    fn walk_to(&mut self, to: GridCoord) -> u32 {
+        self.requests = self.requests.wrapping_add(1);
+        self.started = true;
+        self.path_request = Some(self.requests);
+        self.destination = Some(to);
+        self.requests
+    }
+}
+
+/// What carrying out orders reads and updates of each unit
+type OrderedUnit<'a> = (
+    Entity,
+    &'a Unit,
+    &'a Ownership,
+    &'a mut Orders,
+    &'a Statsheet,
+    &'a Transform,
+    Has<MovePath>,
+    Has<FormationSlot>,
+);
+
+/// Units orders can be aimed at, and the index to look for them in
+#[derive(SystemParam)]
+pub struct OrderTargets<'w, 's> {
+    index: Res<'w, SpatialIndex>,
+    units: Query<'w, 's, &'static Transform, (With<Unit>, Without<Corpse>)>,
+}
+
+impl OrderTargets<'_, '_> {
+    /// Position of a living unit
+    fn position(&self, entity: Entity) -> Option<Vec3> {
+        self.units.get(entity).ok().map(|transform| transform.translation)
+    }
+
+    /// Closest enemy of `ownership` within `range` of `from` that can be seen from there
+    fn nearest_enemy(&self, cells: &GridCells, from: Vec3, ownership: &Ownership, range: f32) -> Option<Entity> {
+        let planar_distance_sq = |to: Vec3| Vec2::new(to.x - from.x, to.z - from.z).length_squared();
+        self.index
+            .query_radius(from, range, &OwnershipFilter::HostileTo(ownership.clone()))
+            .into_iter()
+            .filter_map(|entity| Some((entity, self.position(entity)?)))
+            .filter(|(_, position)| cells.in_sight_range(from, *position, range))
+            .min_by(|a, b| planar_distance_sq(a.1).total_cmp(&planar_distance_sq(b.1)))
+            .map(|(entity, _)| entity)
+    }
+}
+
+/// Put new orders into units' queues; unqueued orders interrupt what units are doing
+pub fn issue_orders(
+    mut commands: Commands,
+    mut events: EventReader<IssueOrderEvent>,
+    mut units: Query<&mut Orders, Without<Corpse>>,
+) {
+    for event in events.read() {
+        let Ok(mut orders) = units.get_mut(event.entity) else {
+            continue;
+        };
+        orders.formation = event.formation;
+        if event.queued && orders.active().is_some() {
+            orders.queue.push_back(event.order.clone());
+            continue;
+        }
+        orders.replace(event.order.clone());
+        commands.entity(event.entity).remove::<(MovePath, FormationSlot)>();
+    }
+}
+
+/// Note when the paths asked for by orders arrive; an order with no path is given up
+pub fn track_order_paths(
+    mut results: EventReader<PathfindingResultEvent>,
+    mut units: Query<&mut Orders>,
+) {
+    for result in results.read() {
+        let Ok(mut orders) = units.get_mut(result.entity) else {
+            continue;
+        };
+        // Results of requests the unit no longer waits on are stale
+        if !orders.awaits_path(result.request) {
+            continue;
+        }
+        orders.path_request = None;
+        if !result.success {
+            orders.advance();
+        }
+    }
+}
+
+/// Carry out each unit's active order, moving on to the next when it is done. Units
+/// starting a move to the same cell in the same formation shape walk there together. Buildings can't
+/// walk, so they drop orders that need them to. Attacks and spells need their target
+/// in range and in sight.
+pub fn execute_orders(
+    mut commands: Commands,
+    cells: GridCells,
+    time: Res<Time>,
+    mut units: Query<OrderedUnit, Without<Corpse>>,
+    targets: OrderTargets,
+    mut path_requests: EventWriter<PathfindingRequestEvent>,
+    mut group_moves: EventWriter<GroupMoveEvent>,
+) {
+    let grid = &cells.grid;
+    let timestamp = time.elapsed_secs_f64();
+    let mut walks: Vec<(Entity, GridCoord, GridCoord, u32)> = Vec::new();
+    let mut group_walks: HashMap<(GridCoord, FormationShape), Vec<(Entity, u32)>> = HashMap::new();
+
+    for (entity, unit, ownership, mut orders, stats, transform, has_path, in_formation) in units.iter_mut() {
+        let position = transform.translation;
+        let mobile = unit.unit_type != UnitType::Building;
+        // Formations walk their members without a path result of their own
+        if in_formation {
+            orders.path_request = None;
+        }
+        let walking = orders.path_request.is_some() || has_path || in_formation;
+        let here = grid.world_to_grid(position);
+        let stop = |commands: &mut Commands| {
+            if has_path || in_formation {
+                commands.entity(entity).remove::<(MovePath, FormationSlot)>();
+            }
+        };
+
+        for _ in 0..MAX_ORDERS_PER_FRAME {
+            let Some(order) = orders.active().cloned() else {
+                break;
+            };
+            let started = orders.started;
+            if !mobile && order.moves_unit() {
+                orders.advance();
+                continue;
+            }
+
+            match order {
+                Order::Stop => {
+                    stop(&mut commands);
+                    orders.advance();
+                    continue;
+                }
+                Order::HoldPosition => {
+                    if !started {
+                        stop(&mut commands);
+                        orders.started = true;
+                    }
+                }
+                Order::Move(to) | Order::AttackMove(to) => {
+                    // Engage the closest enemy in sight, walking on once it is dealt with
+                    if matches!(order, Order::AttackMove(_))
+                        && let Some(enemy) = targets.nearest_enemy(&cells, position, ownership, stats.sight_range)
+                    {
+                        stop(&mut commands);
+                        orders.interrupt(Order::Attack(enemy));
+                        continue;
+                    }
+                    if !started {
+                        let request = orders.walk_to(to);
+                        group_walks.entry((to, orders.formation)).or_default().push((entity, request));
+                    } else if !walking {
+                        orders.advance();
+                        continue;
+                    }
+                }
+                Order::Patrol(to) => {
+                    if !started {
+                        orders.patrol_return = Some(here);
+                        let request = orders.walk_to(to);
+                        walks.push((entity, here, to, request));
+                    } else if !walking {
+                        // Turn around at either end of the route
+                        let back = orders.patrol_return.unwrap_or(here);
+                        orders.patrol_return = orders.destination;
+                        let request = orders.walk_to(back);
+                        walks.push((entity, here, back, request));
+                    }
+                }
+                Order::Attack(target) | Order::Follow(target) => {
+                    let Some(target_position) = targets.position(target) else {
+                        // The target died or is gone
+                        stop(&mut commands);
+                        orders.advance();
+                        continue;
+                    };
+                    let in_range = match order {
+                        Order::Attack(_) => cells.in_sight_range(position, target_position, stats.attack_range),
+                        _ => {
+                            let offset = target_position - position;
+                            Vec2::new(offset.x, offset.z).length() <= FOLLOW_DISTANCE * grid.cell_size
+                        }
+                    };
+                    if in_range {
+                        stop(&mut commands);
+                        orders.halt();
+                    } else if !mobile {
+                        orders.advance();
+                        continue;
+                    } else {
+                        // Chase the target's current cell
+                        let target_cell = grid.world_to_grid(target_position);
+                        if orders.path_request.is_none() && orders.destination != Some(target_cell) {
+                            let request = orders.walk_to(target_cell);
+                            walks.push((entity, here, target_cell, request));
+                        }
+                    }
+                }
+                Order::Harvest(at) => {
+                    if !started {
+                        let request = orders.walk_to(at);
+                        walks.push((entity, here, at, request));
+                    }
+                }
+                Order::Build { at, .. } => {
+                    if !started {
+                        let request = orders.walk_to(at);
+                        walks.push((entity, here, at, request));
+                    } else if !walking {
+                        orders.advance();
+                        continue;
+                    }
+                }
+                Order::Cast { target, .. } => {
+                    let point = match target {
+                        CastTarget::None => None,
+                        CastTarget::Unit(target) => {
+                            let Some(target_position) = targets.position(target) else {
+                                // The target died or is gone
+                                stop(&mut commands);
+                                orders.advance();
+                                continue;
+                            };
+                            Some(target_position)
+                        }
+                        CastTarget::Point(coord) => Some(grid.grid_to_world(coord)),
+                    };
+                    let in_range = point.is_none_or(|point| cells.in_sight_range(position, point, stats.attack_range));
+                    if in_range {
+                        // Stand still and cast, finishing once the spell is done
+                        stop(&mut commands);
+                        orders.halt();
+                        orders.elapsed += time.delta_secs();
+                        if orders.elapsed >= CAST_TIME {
+                            orders.advance();
+                            continue;
+                        }
+                    } else if !mobile {
+                        orders.advance();
+                        continue;
+                    } else if let Some(point) = point {
+                        // Walk up to the target, following it if it moves
+                        let to = grid.world_to_grid(point);
+                        if orders.path_request.is_none() && orders.destination != Some(to) {
+                            let request = orders.walk_to(to);
+                            walks.push((entity, here, to, request));
+                        }
+                    }
+                }
+            }
+            break;
+        }
+    }
+
+    for ((to, shape), members) in group_walks {
+        if members.len() > 1 {
+            let units = members.into_iter().map(|(entity, _)| entity).collect();
+            group_moves.write(GroupMoveEvent { units, to, shape, timestamp });
+            continue;
+        }
+        for (entity, request) in members {
+            if let Ok((_, _, _, _, _, transform, _, _)) = units.get(entity) {
+                walks.push((entity, grid.world_to_grid(transform.translation), to, request));
+            }
+        }
+    }
+    for (entity, from, to, request) in walks {
+        path_requests.write(PathfindingRequestEvent {
+            entity,
+            request,
+            from,
+            to,
+            movement: MovementClass::Ground,
+            timestamp,
+        });
+    }
+}
+
+/// What a unit's state is derived from
+type UnitProgress<'a> = (&'a mut Unit, &'a Orders, Has<MovePath>, Has<FormationSlot>);
+
+/// Derive units' state and target from their active order
+pub fn sync_unit_state(
+    mut units: Query<UnitProgress, Without<Corpse>>,
+) {
+    for (mut unit, orders, has_path, in_formation) in units.iter_mut() {
+        if unit.state == UnitState::Dead {
+            continue;
+        }
+        let walking = orders.path_request.is_some() || has_path || in_formation;
+        let (state, target) = match orders.active() {
+            None | Some(Order::Stop) | Some(Order::HoldPosition) => (UnitState::Idle, None),
+            Some(Order::Move(_)) | Some(Order::AttackMove(_)) | Some(Order::Patrol(_)) => (UnitState::Moving, None),
+            Some(Order::Follow(target)) => (if walking { UnitState::Moving } else { UnitState::Idle }, Some(*target)),
+            Some(Order::Attack(target)) => (if walking { UnitState::Moving } else { UnitState::Attacking }, Some(*target)),
+            Some(Order::Harvest(_)) => (if walking { UnitState::Moving } else { UnitState::Harvesting }, None),
+            Some(Order::Build { .. }) => (if walking { UnitState::Moving } else { UnitState::Constructing }, None),
+            Some(Order::Cast { target, .. }) => {
+                let target = match target {
+                    CastTarget::Unit(target) => Some(*target),
+                    _ => None,
+                };
+                (if walking { UnitState::Moving } else { UnitState::Casting }, target)
+            }
+        };
+
+        // Only write on change, so systems watching units don't see every unit as changed
+        if unit.state != state || unit.target != target {
+            unit.state = state;
+            unit.target = target;
+        }
+    }
+}
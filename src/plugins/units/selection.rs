@@ -0,0 +1,124 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::ui::RelativeCursorPosition;
+use std::collections::HashSet;
+use std::f32::consts::FRAC_PI_2;
+use crate::components::faction::{LocalPlayer, Ownership};
+use crate::components::unit::{Selected, Statsheet, Unit};
+use crate::plugins::camera::CursorPick;
+use crate::plugins::map::{OwnershipFilter, SpatialIndex};
+use super::formation::FormationChoice;
+use super::orders::{IssueOrderEvent, Order};
+use super::Corpse;
+
+/// Ground distance, in world units, the cursor has to travel for a click to become a box
+const DRAG_THRESHOLD: f32 = 0.5;
+/// Height of selection markers above the ground under a unit
+const MARKER_OFFSET: f32 = 0.05;
+
+/// Units the local player could select
+type Selectable = (With<Unit>, Without<Corpse>);
+
+/// Ground point where the current selection drag started
+#[derive(Resource, Debug, Default)]
+pub struct SelectionDrag {
+    start: Option<Vec3>,
+}
+
+/// Mouse and keyboard state selection reads
+#[derive(SystemParam)]
+pub struct SelectionInput<'w, 's> {
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    pick: Res<'w, CursorPick>,
+    ui: Query<'w, 's, &'static RelativeCursorPosition>,
+}
+
+impl SelectionInput<'_, '_> {
+    /// Whether the cursor is over the minimap or other UI, whose clicks are their own
+    fn over_ui(&self) -> bool {
+        self.ui.iter().any(RelativeCursorPosition::mouse_over)
+    }
+}
+
+/// Left click selects the local player's unit under the cursor, dragging selects every
+/// one of their units inside the box. Shift adds to the selection instead of replacing it.
+pub fn select_units(
+    mut commands: Commands,
+    input: SelectionInput,
+    local_player: Res<LocalPlayer>,
+    index: Res<SpatialIndex>,
+    mut drag: ResMut<SelectionDrag>,
+    units: Query<(Entity, &Ownership, Has<Selected>), Selectable>,
+) {
+    if input.mouse.just_pressed(MouseButton::Left) {
+        drag.start = if input.over_ui() { None } else { input.pick.world_point };
+    }
+    if !input.mouse.just_released(MouseButton::Left) {
+        return;
+    }
+    let Some(start) = drag.start.take() else {
+        return;
+    };
+
+    let end = input.pick.world_point.unwrap_or(start);
+    let dragged = Vec2::new(end.x - start.x, end.z - start.z).length() > DRAG_THRESHOLD;
+    let candidates = if dragged {
+        index.query_rect(start, end, &OwnershipFilter::Faction(local_player.faction.clone()))
+    } else {
+        input.pick.unit.into_iter().collect()
+    };
+    // Only the local player's own living units can be selected
+    let picked: HashSet<Entity> = candidates
+        .into_iter()
+        .filter(|entity| units.get(*entity).is_ok_and(|(_, ownership, _)| ownership.faction == local_player.faction))
+        .collect();
+
+    let adding = input.keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    for (entity, _, selected) in units.iter() {
+        let keep = picked.contains(&entity) || (adding && selected);
+        if keep && !selected {
+            commands.entity(entity).insert(Selected);
+        } else if !keep && selected {
+            commands.entity(entity).remove::<Selected>();
+        }
+    }
+}
+
+/// System parameter giving orders to the local player's selected units
+#[derive(SystemParam)]
+pub struct SelectedUnits<'w, 's> {
+    units: Query<'w, 's, Entity, (With<Unit>, With<Selected>)>,
+    formation: Res<'w, FormationChoice>,
+    orders: EventWriter<'w, IssueOrderEvent>,
+}
+
+impl SelectedUnits<'_, '_> {
+    /// Give every selected unit the order, moving in the chosen formation
+    pub fn order(&mut self, order: Order, queued: bool) {
+        for entity in self.units.iter() {
+            self.orders.write(IssueOrderEvent { entity, order: order.clone(), queued, formation: self.formation.0 });
+        }
+    }
+}
+
+/// Ring selected units and outline the box being dragged
+pub fn draw_selection(
+    drag: Res<SelectionDrag>,
+    pick: Res<CursorPick>,
+    selected: Query<(&Transform, &Statsheet), With<Selected>>,
+    mut gizmos: Gizmos,
+) {
+    let flat = Quat::from_rotation_x(-FRAC_PI_2);
+    let color = Color::srgb(0.2, 1.0, 0.3);
+    for (transform, stats) in selected.iter() {
+        let center = transform.translation.with_y(transform.translation.y + MARKER_OFFSET);
+        gizmos.circle(Isometry3d::new(center, flat), stats.collision_radius * 1.2, color);
+    }
+
+    if let (Some(start), Some(end)) = (drag.start, pick.world_point) {
+        let center = (start + end) / 2.0 + Vec3::Y * MARKER_OFFSET;
+        let size = Vec2::new((end.x - start.x).abs(), (end.z - start.z).abs());
+        gizmos.rect(Isometry3d::new(center, flat), size, color);
+    }
+}